@@ -0,0 +1,300 @@
+//! Generates `nsContentPolicyType` from the `cenum nsContentPolicyType` block
+//! of `nsIContentPolicy.idl`.
+//!
+//! The vendored copy in `idl/` is used unless `MOZILLA_CENTRAL` points at a
+//! mozilla-central checkout, in which case its `dom/base/nsIContentPolicy.idl`
+//! is read instead.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IDL_IN_CHECKOUT: &str = "dom/base/nsIContentPolicy.idl";
+const VENDORED_IDL: &str = "idl/nsIContentPolicy.idl";
+
+/// What `nsContentUtils::InternalContentPolicyTypeToExternal` maps each
+/// internal type to. The IDL does not say, so this has to follow Gecko by
+/// hand; every `TYPE_INTERNAL_*` missing here gets a build warning. Entries
+/// for types an older IDL does not have yet are skipped.
+const TO_EXTERNAL: &[(&str, &str)] = &[
+    ("TYPE_INTERNAL_SCRIPT", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_SCRIPT_PRELOAD", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_MODULE", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_MODULE_PRELOAD", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_WORKER", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_SHARED_WORKER", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_SERVICE_WORKER", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_WORKER_IMPORT_SCRIPTS", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_WORKER_STATIC_MODULE", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_AUDIOWORKLET", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_PAINTWORKLET", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_CHROMEUTILS_COMPILED_SCRIPT", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_FRAME_MESSAGEMANAGER_SCRIPT", "TYPE_SCRIPT"),
+    ("TYPE_INTERNAL_EMBED", "TYPE_OBJECT"),
+    ("TYPE_INTERNAL_OBJECT", "TYPE_OBJECT"),
+    ("TYPE_INTERNAL_FRAME", "TYPE_SUBDOCUMENT"),
+    ("TYPE_INTERNAL_IFRAME", "TYPE_SUBDOCUMENT"),
+    ("TYPE_INTERNAL_AUDIO", "TYPE_MEDIA"),
+    ("TYPE_INTERNAL_VIDEO", "TYPE_MEDIA"),
+    ("TYPE_INTERNAL_TRACK", "TYPE_MEDIA"),
+    ("TYPE_INTERNAL_XMLHTTPREQUEST", "TYPE_XMLHTTPREQUEST"),
+    ("TYPE_INTERNAL_EVENTSOURCE", "TYPE_XMLHTTPREQUEST"),
+    ("TYPE_INTERNAL_IMAGE", "TYPE_IMAGE"),
+    ("TYPE_INTERNAL_IMAGE_PRELOAD", "TYPE_IMAGE"),
+    ("TYPE_INTERNAL_IMAGE_FAVICON", "TYPE_IMAGE"),
+    ("TYPE_INTERNAL_STYLESHEET", "TYPE_STYLESHEET"),
+    ("TYPE_INTERNAL_STYLESHEET_PRELOAD", "TYPE_STYLESHEET"),
+    ("TYPE_INTERNAL_DTD", "TYPE_DTD"),
+    ("TYPE_INTERNAL_FORCE_ALLOWED_DTD", "TYPE_DTD"),
+    ("TYPE_INTERNAL_FONT_PRELOAD", "TYPE_FONT"),
+    ("TYPE_UA_FONT", "TYPE_FONT"),
+    ("TYPE_INTERNAL_FETCH_PRELOAD", "TYPE_FETCH"),
+];
+
+/// Internal types for a worker's main script and the scripts it imports.
+/// Worklets do not count.
+const WORKERS: &[&str] = &[
+    "TYPE_INTERNAL_WORKER",
+    "TYPE_INTERNAL_SHARED_WORKER",
+    "TYPE_INTERNAL_SERVICE_WORKER",
+    "TYPE_INTERNAL_WORKER_IMPORT_SCRIPTS",
+    "TYPE_INTERNAL_WORKER_STATIC_MODULE",
+];
+
+/// The `PolicyCategory` of external types. Everything else is `Other`.
+const CATEGORIES: &[(&str, &str)] = &[
+    ("TYPE_SCRIPT", "Script"),
+    ("TYPE_STYLESHEET", "Style"),
+    ("TYPE_XSLT", "Style"),
+    ("TYPE_IMAGE", "Image"),
+    ("TYPE_IMAGESET", "Image"),
+    ("TYPE_MEDIA", "Media"),
+    ("TYPE_DOCUMENT", "Document"),
+    ("TYPE_SUBDOCUMENT", "Document"),
+    ("TYPE_XMLHTTPREQUEST", "Fetch"),
+    ("TYPE_FETCH", "Fetch"),
+    ("TYPE_BEACON", "Fetch"),
+    ("TYPE_PING", "Fetch"),
+    ("TYPE_WEBSOCKET", "Fetch"),
+    ("TYPE_WEB_TRANSPORT", "Fetch"),
+    ("TYPE_CSP_REPORT", "Fetch"),
+    ("TYPE_FONT", "Font"),
+];
+
+struct PolicyType {
+    name: String,
+    value: u32,
+    doc: Vec<String>,
+}
+
+/// Turns the lines of a `/** ... */` comment into doc comment lines.
+fn doc_lines(comment: &[&str]) -> Vec<String> {
+    let mut lines: Vec<String> = comment
+        .iter()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix("/**").unwrap_or(line);
+            let line = line.strip_suffix("*/").unwrap_or(line);
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ')
+                .unwrap_or(line)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+    while lines.first().is_some_and(|l| l.is_empty()) {
+        lines.remove(0);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn parse_cenum(idl: &str) -> Vec<PolicyType> {
+    let mut lines = idl
+        .lines()
+        .skip_while(|line| !line.contains("cenum nsContentPolicyType"))
+        .skip(1);
+    let mut types = vec![];
+    let mut doc = vec![];
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('}') {
+            break;
+        } else if trimmed.starts_with("/*") {
+            let mut comment = vec![line];
+            let mut end = line;
+            while !end.trim_end().ends_with("*/") {
+                match lines.next() {
+                    Some(next) => {
+                        comment.push(next);
+                        end = next;
+                    }
+                    None => break,
+                }
+            }
+            // Plain `/*` comments are notes about removed values and the like.
+            doc = if trimmed.starts_with("/**") {
+                doc_lines(&comment)
+            } else {
+                vec![]
+            };
+        } else if let Some((name, value)) = trimmed.trim_end_matches(',').split_once('=') {
+            let value = value.trim();
+            types.push(PolicyType {
+                name: name.trim().to_string(),
+                value: value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("unexpected value {:?} for {}", value, name)),
+                doc: std::mem::take(&mut doc),
+            });
+        }
+    }
+    assert!(!types.is_empty(), "no cenum nsContentPolicyType found");
+    types
+}
+
+fn generate(types: &[PolicyType]) -> String {
+    let mut out = String::new();
+    out.push_str("/// The type of nsIContentPolicy::TYPE_*\n");
+    out.push_str("#[allow(non_camel_case_types)]\n");
+    out.push_str("#[repr(u32)]\n");
+    out.push_str(
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, IntoStaticStr, \
+         Serialize, Deserialize)]\n",
+    );
+    out.push_str("pub enum nsContentPolicyType {\n");
+    for t in types {
+        for line in &t.doc {
+            if line.is_empty() {
+                out.push_str("    ///\n");
+            } else {
+                out.push_str(&format!("    /// {}\n", line));
+            }
+        }
+        out.push_str(&format!("    {} = {},\n", t.name, t.value));
+    }
+    out.push_str("}\n\n");
+
+    out.push_str("impl nsContentPolicyType {\n");
+    out.push_str("    /// All types, in the order of the IDL.\n");
+    out.push_str("    pub const ALL: &[nsContentPolicyType] = &[\n");
+    for t in types {
+        out.push_str(&format!("        nsContentPolicyType::{},\n", t.name));
+    }
+    out.push_str("    ];\n}\n\n");
+
+    out.push_str("impl std::convert::TryFrom<u32> for nsContentPolicyType {\n");
+    out.push_str("    type Error = u32;\n\n");
+    out.push_str("    fn try_from(value: u32) -> Result<Self, Self::Error> {\n");
+    out.push_str("        match value {\n");
+    for t in types {
+        out.push_str(&format!(
+            "            {} => Ok(nsContentPolicyType::{}),\n",
+            t.value, t.name
+        ));
+    }
+    out.push_str("            other => Err(other),\n");
+    out.push_str("        }\n    }\n}\n\n");
+
+    out.push_str("impl nsContentPolicyType {\n");
+    out.push_str(
+        "    /// The type content policies get to see for this one, following\n    \
+         /// `nsContentUtils::InternalContentPolicyTypeToExternal`. External\n    \
+         /// types map to themselves.\n",
+    );
+    out.push_str("    pub fn to_external(self) -> nsContentPolicyType {\n");
+    out.push_str("        match self {\n");
+    for t in types {
+        out.push_str(&format!(
+            "            nsContentPolicyType::{} => nsContentPolicyType::{},\n",
+            t.name,
+            external(types, &t.name)
+        ));
+    }
+    out.push_str("        }\n    }\n\n");
+
+    out.push_str(
+        "    /// Speculative loads triggered by `<link rel=preload>` and the HTML\n    \
+         /// preload scanner.\n",
+    );
+    out.push_str("    pub fn is_preload(self) -> bool {\n");
+    out.push_str("        match self {\n");
+    for t in types {
+        let preload = t.name.starts_with("TYPE_INTERNAL_") && t.name.ends_with("_PRELOAD");
+        out.push_str(&format!(
+            "            nsContentPolicyType::{} => {},\n",
+            t.name, preload
+        ));
+    }
+    out.push_str("        }\n    }\n\n");
+
+    out.push_str(
+        "    /// Loads of a worker's main script and of scripts it imports. Worklets\n    \
+         /// do not count.\n",
+    );
+    out.push_str("    pub fn is_worker(self) -> bool {\n");
+    out.push_str("        match self {\n");
+    for t in types {
+        out.push_str(&format!(
+            "            nsContentPolicyType::{} => {},\n",
+            t.name,
+            WORKERS.contains(&t.name.as_str())
+        ));
+    }
+    out.push_str("        }\n    }\n\n");
+
+    out.push_str("    /// The category of the external type this one maps to.\n");
+    out.push_str("    pub fn category(self) -> PolicyCategory {\n");
+    out.push_str("        match self {\n");
+    for t in types {
+        let external = external(types, &t.name);
+        let category = CATEGORIES
+            .iter()
+            .find(|(name, _)| *name == external)
+            .map_or("Other", |(_, category)| category);
+        out.push_str(&format!(
+            "            nsContentPolicyType::{} => PolicyCategory::{},\n",
+            t.name, category
+        ));
+    }
+    out.push_str("        }\n    }\n}\n");
+    out
+}
+
+/// The external type of `name`, see `TO_EXTERNAL`.
+fn external<'a>(types: &[PolicyType], name: &'a str) -> &'a str {
+    match TO_EXTERNAL.iter().find(|(internal, _)| *internal == name) {
+        Some((_, external)) if types.iter().any(|t| t.name == *external) => external,
+        _ => name,
+    }
+}
+
+/// Warns about internal types `TO_EXTERNAL` does not know, which would
+/// otherwise silently count as external.
+fn check_mappings(types: &[PolicyType]) {
+    for t in types {
+        if t.name.starts_with("TYPE_INTERNAL_") && external(types, &t.name) == t.name {
+            println!(
+                "cargo:warning={} has no external type in build.rs, treating it as external",
+                t.name
+            );
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=MOZILLA_CENTRAL");
+    let idl_path = match env::var_os("MOZILLA_CENTRAL") {
+        Some(checkout) => Path::new(&checkout).join(IDL_IN_CHECKOUT),
+        None => PathBuf::from(VENDORED_IDL),
+    };
+    println!("cargo:rerun-if-changed={}", idl_path.display());
+    let idl = fs::read_to_string(&idl_path)
+        .unwrap_or_else(|e| panic!("can not read {}: {}", idl_path.display(), e));
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("policytypes.rs");
+    let types = parse_cenum(&idl);
+    check_mappings(&types);
+    fs::write(out, generate(&types)).unwrap();
+}
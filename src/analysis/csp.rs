@@ -0,0 +1,449 @@
+//! Offline evaluation of a logged CSP against the load it was logged with,
+//! following the matching algorithms of CSP Level 3.
+
+use crate::analysis::rules::{Finding, Rule, Severity};
+use crate::parsing::checktypes::ContentSecurityCheck;
+use crate::parsing::csp::{DirectiveName, Keyword, Policy, SourceExpression};
+use crate::parsing::origin::origin_of;
+use crate::parsing::policytypes::{nsContentPolicyType, PolicyCategory};
+use crate::parsing::principal::Principal;
+use std::fmt;
+use url::{Origin, Url};
+
+/// The directives consulted for a load of type `policy_type`, most specific
+/// first. Loads CSP does not govern (top-level documents, CSP reports, ...)
+/// have none.
+pub fn directive_fallbacks(policy_type: nsContentPolicyType) -> Vec<DirectiveName> {
+    use nsContentPolicyType::*;
+    use DirectiveName::*;
+    match policy_type {
+        // Scripts a worker imports fall under script-src like any other.
+        TYPE_INTERNAL_WORKER_IMPORT_SCRIPTS => vec![ScriptSrcElem, ScriptSrc, DefaultSrc],
+        _ if policy_type.is_worker() => vec![WorkerSrc, ChildSrc, ScriptSrc, DefaultSrc],
+        _ => match policy_type.to_external() {
+            TYPE_SCRIPT => vec![ScriptSrcElem, ScriptSrc, DefaultSrc],
+            TYPE_STYLESHEET => vec![StyleSrcElem, StyleSrc, DefaultSrc],
+            TYPE_IMAGE | TYPE_IMAGESET => vec![ImgSrc, DefaultSrc],
+            TYPE_MEDIA => vec![MediaSrc, DefaultSrc],
+            TYPE_FONT => vec![FontSrc, DefaultSrc],
+            TYPE_OBJECT => vec![ObjectSrc, DefaultSrc],
+            TYPE_SUBDOCUMENT => vec![FrameSrc, ChildSrc, DefaultSrc],
+            TYPE_WEB_MANIFEST => vec![ManifestSrc, DefaultSrc],
+            TYPE_CSP_REPORT => vec![],
+            _ if policy_type.category() == PolicyCategory::Fetch => vec![ConnectSrc, DefaultSrc],
+            _ => vec![],
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    Blocked,
+    /// Only a nonce, hash or `'strict-dynamic'` could allow the load, and
+    /// the log does not tell whether the element carried one.
+    Unknown,
+}
+
+/// How one policy treats one load.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// The directive governing the load, `None` if no directive applies.
+    pub directive: Option<DirectiveName>,
+    pub verdict: Verdict,
+    /// Whether `upgrade-insecure-requests` turned the URL into HTTPS first.
+    pub upgraded: bool,
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verdict = match self.verdict {
+            Verdict::Allowed => "allowed",
+            Verdict::Blocked => "blocked",
+            Verdict::Unknown => "undecidable",
+        };
+        match &self.directive {
+            Some(directive) => write!(f, "{} by {}", verdict, directive)?,
+            None => write!(f, "{}, no directive applies", verdict)?,
+        }
+        if self.upgraded {
+            write!(f, " (after upgrade-insecure-requests)")?;
+        }
+        Ok(())
+    }
+}
+
+/// CSP's "scheme-part match": `http:` also allows the secure variant.
+fn scheme_matches(expression: &str, scheme: &str) -> bool {
+    expression == scheme
+        || (expression == "http" && scheme == "https")
+        || (expression == "ws" && ["wss", "http", "https"].contains(&scheme))
+        || (expression == "wss" && scheme == "https")
+}
+
+fn host_matches(expression: &str, host: &str) -> bool {
+    match expression.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+        None => expression == host,
+    }
+}
+
+fn port_matches(expression: Option<&str>, expression_scheme: Option<&str>, url: &Url) -> bool {
+    let url_port = url.port_or_known_default();
+    match expression {
+        Some("*") => true,
+        Some(port) => {
+            let port: Option<u16> = port.parse().ok();
+            port == url_port || (port == Some(80) && url_port == Some(443))
+        }
+        // No port allows the default port of the scheme, including the
+        // upgrade from http's to https'.
+        None => {
+            url.port().is_none()
+                || (expression_scheme.is_none_or(|s| s == "http") && url_port == Some(443))
+        }
+    }
+}
+
+fn path_matches(expression: &str, url: &Url) -> bool {
+    if expression.ends_with('/') {
+        url.path().starts_with(expression)
+    } else {
+        url.path() == expression
+    }
+}
+
+/// Whether `url` matches one source expression. `this` is the origin of the
+/// loading principal, `redirected` whether the load followed a redirect
+/// (paths are ignored then).
+fn source_matches(
+    expression: &SourceExpression,
+    url: &Url,
+    this: Option<&Origin>,
+    redirected: bool,
+) -> bool {
+    let this_scheme = match this {
+        Some(Origin::Tuple(scheme, _, _)) => Some(scheme.as_str()),
+        _ => None,
+    };
+    match expression {
+        SourceExpression::Scheme(scheme) => scheme_matches(scheme, url.scheme()),
+        SourceExpression::Host {
+            scheme: None,
+            host,
+            port: None,
+            path: None,
+        } if host == "*" => {
+            // `*` does not allow data:, blob: and friends.
+            ["http", "https", "ws", "wss", "ftp"].contains(&url.scheme())
+                || this_scheme == Some(url.scheme())
+        }
+        SourceExpression::Host {
+            scheme,
+            host,
+            port,
+            path,
+        } => {
+            let scheme_ok = match scheme {
+                Some(scheme) => scheme_matches(scheme, url.scheme()),
+                None => scheme_matches(this_scheme.unwrap_or("http"), url.scheme()),
+            };
+            let url_host = match url.host_str() {
+                Some(url_host) => url_host.to_ascii_lowercase(),
+                None => return false,
+            };
+            scheme_ok
+                && host_matches(host, &url_host)
+                && port_matches(port.as_deref(), scheme.as_deref(), url)
+                && (redirected || path.as_deref().is_none_or(|p| path_matches(p, url)))
+        }
+        SourceExpression::Keyword(Keyword::SelfOrigin) => match this {
+            Some(this @ Origin::Tuple(scheme, host, port)) => {
+                // 'self' also allows the upgrade from http: to https:.
+                origin_of(url) == *this
+                    || (scheme == "http"
+                        && url.scheme() == "https"
+                        && url.host_str() == Some(host.to_string().as_str())
+                        && (url.port().is_none() || url.port() == Some(*port)))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Evaluates `policy` for a load of `url` of type `policy_type` by
+/// `loading_principal`.
+pub fn evaluate_load(
+    policy: &Policy,
+    url: &Url,
+    policy_type: nsContentPolicyType,
+    loading_principal: &Principal,
+    redirected: bool,
+) -> Evaluation {
+    let directive = directive_fallbacks(policy_type)
+        .iter()
+        .find_map(|name| policy.directive(name));
+    let directive = match directive {
+        Some(directive) => directive,
+        None => {
+            return Evaluation {
+                directive: None,
+                verdict: Verdict::Allowed,
+                upgraded: false,
+            }
+        }
+    };
+
+    let mut url = url.clone();
+    let upgraded = policy.upgrade_insecure_requests()
+        && match url.scheme() {
+            "http" => url.set_scheme("https").is_ok(),
+            "ws" => url.set_scheme("wss").is_ok(),
+            _ => false,
+        };
+    if upgraded && url.port() == Some(80) {
+        let _ = url.set_port(None);
+    }
+
+    let this = loading_principal.origin();
+    let matched = directive
+        .sources
+        .iter()
+        .any(|s| source_matches(s, &url, this.as_ref(), redirected));
+    let is_script_or_style = policy_type.to_external() == nsContentPolicyType::TYPE_SCRIPT
+        || policy_type.to_external() == nsContentPolicyType::TYPE_STYLESHEET;
+    let verdict = if directive.has_keyword(Keyword::StrictDynamic) && is_script_or_style {
+        // Host and scheme sources are ignored under 'strict-dynamic'.
+        Verdict::Unknown
+    } else if matched {
+        Verdict::Allowed
+    } else if is_script_or_style
+        && directive.sources.iter().any(|s| {
+            matches!(
+                s,
+                SourceExpression::Nonce(_) | SourceExpression::Hash { .. }
+            )
+        })
+    {
+        Verdict::Unknown
+    } else {
+        Verdict::Blocked
+    };
+    Evaluation {
+        directive: Some(directive.name.clone()),
+        verdict,
+        upgraded,
+    }
+}
+
+/// Evaluates every logged policy of `check` for its own load. Checks whose
+/// channel URI is no URL or whose type we do not know are skipped.
+pub fn evaluate_check(check: &ContentSecurityCheck) -> Vec<(Policy, Evaluation)> {
+    let url = match Url::parse(check.channel_uri()) {
+        Ok(url) => url,
+        Err(_) => return vec![],
+    };
+    let policy_type = match check.internal_content_policy_type().known() {
+        Some(policy_type) => policy_type,
+        None => return vec![],
+    };
+    let redirected = check.redirect_chain().is_some_and(|c| !c.is_empty());
+    check
+        .csp_policies()
+        .into_iter()
+        .map(|policy| {
+            let evaluation = evaluate_load(
+                &policy,
+                &url,
+                policy_type,
+                check.loading_principal(),
+                redirected,
+            );
+            (policy, evaluation)
+        })
+        .collect()
+}
+
+/// Loads one of the logged policies would block.
+pub struct CspBlocked;
+
+impl Rule for CspBlocked {
+    fn id(&self) -> &'static str {
+        "csp-blocked"
+    }
+
+    fn group(&self) -> &'static str {
+        "csp"
+    }
+
+    fn description(&self) -> &'static str {
+        "loads the logged CSP would block"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, check: &ContentSecurityCheck) -> Option<Finding> {
+        let details: Vec<String> = evaluate_check(check)
+            .into_iter()
+            .filter(|(_, evaluation)| evaluation.verdict == Verdict::Blocked)
+            .map(|(policy, evaluation)| format!("{} under \"{}\"", evaluation, policy))
+            .collect();
+        Finding::of_all(self, &details)
+    }
+}
+
+#[cfg(test)]
+mod tests_evaluate {
+    use super::{evaluate_check, evaluate_load, CspBlocked, Verdict};
+    use crate::analysis::rules::Rule;
+    use crate::parsing::csp::{DirectiveName, Policy};
+    use crate::parsing::policytypes::nsContentPolicyType::{self, *};
+    use crate::parsing::principal::Principal;
+    use crate::parsing::tests::fixtures;
+    use std::str::FromStr;
+    use url::Url;
+
+    fn verdict(policy: &str, url: &str, policy_type: nsContentPolicyType) -> Verdict {
+        let principal = Principal::from_str("https://example.com/page").unwrap();
+        let policy = Policy::parse(policy);
+        evaluate_load(
+            &policy,
+            &Url::parse(url).unwrap(),
+            policy_type,
+            &principal,
+            false,
+        )
+        .verdict
+    }
+
+    #[test]
+    fn fixture() {
+        let check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        let evaluations = evaluate_check(&check);
+        assert_eq!(evaluations.len(), 1);
+        let evaluation = &evaluations[0].1;
+        assert_eq!(evaluation.directive, Some(DirectiveName::DefaultSrc));
+        assert_eq!(evaluation.verdict, Verdict::Allowed);
+        assert_eq!(CspBlocked.check(&check), None);
+    }
+
+    #[test]
+    fn blocked() {
+        let mut check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        check.csp = Some(vec!["script-src 'self'".to_string()]);
+        check.channel_uri = "https://cdn.example.net/a.js".to_string();
+        let finding = CspBlocked.check(&check).unwrap();
+        assert_eq!(finding.rule, "csp-blocked");
+    }
+
+    #[test]
+    fn fallback_chain() {
+        let policy = Policy::parse("default-src 'none'; script-src https:; script-src-elem 'self'");
+        let principal = Principal::from_str("https://example.com/").unwrap();
+        let url = Url::parse("https://cdn.example.net/a.js").unwrap();
+        let script = evaluate_load(&policy, &url, TYPE_INTERNAL_SCRIPT, &principal, false);
+        assert_eq!(script.directive, Some(DirectiveName::ScriptSrcElem));
+        assert_eq!(script.verdict, Verdict::Blocked);
+        let worker = evaluate_load(&policy, &url, TYPE_INTERNAL_WORKER, &principal, false);
+        assert_eq!(worker.directive, Some(DirectiveName::ScriptSrc));
+        assert_eq!(worker.verdict, Verdict::Allowed);
+        let image = evaluate_load(&policy, &url, TYPE_INTERNAL_IMAGE, &principal, false);
+        assert_eq!(image.directive, Some(DirectiveName::DefaultSrc));
+        let document = evaluate_load(&policy, &url, TYPE_DOCUMENT, &principal, false);
+        assert_eq!(document.directive, None);
+        assert_eq!(document.verdict, Verdict::Allowed);
+    }
+
+    #[test]
+    fn self_in_extension() {
+        let policy = Policy::parse("script-src 'self'");
+        let uuid = "6d3f1ba6-5a04-4f84-a7b3-3f2d7c2e9d11";
+        let principal = Principal::from_str(&format!("moz-extension://{}/bg.html", uuid)).unwrap();
+        let own = Url::parse(&format!("moz-extension://{}/bg.js", uuid)).unwrap();
+        let other =
+            Url::parse("moz-extension://0a3c4e1e-7a15-4c3a-9e63-1d4fbc2a0b77/bg.js").unwrap();
+        let verdict = |url| evaluate_load(&policy, url, TYPE_SCRIPT, &principal, false).verdict;
+        assert_eq!(verdict(&own), Verdict::Allowed);
+        assert_eq!(verdict(&other), Verdict::Blocked);
+    }
+
+    #[test]
+    fn self_and_hosts() {
+        let p = "img-src 'self' *.example.org static.example.net:8443/img/";
+        assert_eq!(
+            verdict(p, "https://example.com/x.png", TYPE_IMAGE),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            verdict(p, "https://example.com:444/", TYPE_IMAGE),
+            Verdict::Blocked
+        );
+        assert_eq!(
+            verdict(p, "https://a.example.org/", TYPE_IMAGE),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            verdict(p, "https://example.org/", TYPE_IMAGE),
+            Verdict::Blocked
+        );
+        assert_eq!(
+            verdict(p, "https://static.example.net:8443/img/a.png", TYPE_IMAGE),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            verdict(p, "https://static.example.net:8443/other.png", TYPE_IMAGE),
+            Verdict::Blocked
+        );
+        assert_eq!(
+            verdict("img-src *", "data:image/png,", TYPE_IMAGE),
+            Verdict::Blocked
+        );
+        assert_eq!(
+            verdict("img-src * data:", "data:image/png,", TYPE_IMAGE),
+            Verdict::Allowed
+        );
+    }
+
+    #[test]
+    fn upgrade_insecure_requests() {
+        let principal = Principal::from_str("https://example.com/").unwrap();
+        let url = Url::parse("http://cdn.example.net/a.js").unwrap();
+        let strict = Policy::parse("script-src https://cdn.example.net");
+        let evaluation = evaluate_load(&strict, &url, TYPE_SCRIPT, &principal, false);
+        assert_eq!(evaluation.verdict, Verdict::Blocked);
+        let upgrading =
+            Policy::parse("upgrade-insecure-requests; script-src https://cdn.example.net");
+        let evaluation = evaluate_load(&upgrading, &url, TYPE_SCRIPT, &principal, false);
+        assert_eq!(evaluation.verdict, Verdict::Allowed);
+        assert!(evaluation.upgraded);
+    }
+
+    #[test]
+    fn nonces_are_undecidable() {
+        assert_eq!(
+            verdict(
+                "script-src 'nonce-abc'",
+                "https://evil.example/",
+                TYPE_SCRIPT
+            ),
+            Verdict::Unknown
+        );
+        assert_eq!(
+            verdict(
+                "script-src 'strict-dynamic' https:",
+                "https://a.example/",
+                TYPE_SCRIPT
+            ),
+            Verdict::Unknown
+        );
+        assert_eq!(
+            verdict("img-src 'nonce-abc'", "https://a.example/", TYPE_IMAGE),
+            Verdict::Blocked
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Lints the distinct policies seen in a set of logs for known CSP
+//! weaknesses.
+
+use crate::parsing::checktypes::ContentSecurityCheck;
+use crate::parsing::csp::{Directive, DirectiveName, Keyword, Policy, SourceExpression};
+use crate::parsing::origin::serialize_origin;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CspWeakness {
+    /// `'unsafe-inline'` that no nonce or hash overrides.
+    UnsafeInline,
+    /// `data:` allowed for scripts.
+    DataScripts,
+    /// A host source starting with `*`, like `*` or `*.example.com`.
+    WildcardHost,
+    MissingBaseUri,
+    /// Neither `object-src` nor a `default-src 'none'`.
+    MissingObjectSrc,
+    MissingFrameAncestors,
+    /// Plain `http:` sources.
+    HttpSource,
+    /// `report-uri` is deprecated in favor of `report-to`.
+    ReportUriWithoutReportTo,
+}
+
+impl CspWeakness {
+    pub fn id(self) -> &'static str {
+        match self {
+            CspWeakness::UnsafeInline => "csp-unsafe-inline",
+            CspWeakness::DataScripts => "csp-data-scripts",
+            CspWeakness::WildcardHost => "csp-wildcard-host",
+            CspWeakness::MissingBaseUri => "csp-missing-base-uri",
+            CspWeakness::MissingObjectSrc => "csp-missing-object-src",
+            CspWeakness::MissingFrameAncestors => "csp-missing-frame-ancestors",
+            CspWeakness::HttpSource => "csp-http-source",
+            CspWeakness::ReportUriWithoutReportTo => "csp-report-uri-without-report-to",
+        }
+    }
+}
+
+impl fmt::Display for CspWeakness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CspLint {
+    pub weakness: CspWeakness,
+    pub detail: String,
+}
+
+impl fmt::Display for CspLint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.weakness, self.detail)
+    }
+}
+
+/// The directive governing `names`, most specific first, falling back to
+/// `default-src`.
+fn governing<'a>(policy: &'a Policy, names: &[DirectiveName]) -> Option<&'a Directive> {
+    names
+        .iter()
+        .chain(std::iter::once(&DirectiveName::DefaultSrc))
+        .find_map(|name| policy.directive(name))
+}
+
+pub fn lint_policy(policy: &Policy) -> Vec<CspLint> {
+    use DirectiveName::*;
+    let mut lints = vec![];
+    let mut lint = |weakness, detail: String| lints.push(CspLint { weakness, detail });
+
+    let scripts = governing(policy, &[ScriptSrcElem, ScriptSrc]);
+    let styles = governing(policy, &[StyleSrcElem, StyleSrc]);
+    let mut seen = vec![];
+    // Both usually fall back to the same default-src.
+    for directive in scripts.iter().chain(styles.iter()) {
+        if seen.contains(&&directive.name) {
+            continue;
+        }
+        seen.push(&directive.name);
+        let overridden = directive.has_keyword(Keyword::StrictDynamic)
+            || directive.sources.iter().any(|s| {
+                matches!(
+                    s,
+                    SourceExpression::Nonce(_) | SourceExpression::Hash { .. }
+                )
+            });
+        if directive.has_keyword(Keyword::UnsafeInline) && !overridden {
+            lint(
+                CspWeakness::UnsafeInline,
+                format!(
+                    "{} allows 'unsafe-inline' without nonces or hashes",
+                    directive.name
+                ),
+            );
+        }
+    }
+    if let Some(directive) = scripts {
+        if directive
+            .sources
+            .contains(&SourceExpression::Scheme("data".to_string()))
+        {
+            lint(
+                CspWeakness::DataScripts,
+                format!("{} allows scripts from data:", directive.name),
+            );
+        }
+    }
+
+    for directive in &policy.directives {
+        for source in &directive.sources {
+            match source {
+                SourceExpression::Host { host, .. } if host == "*" => lint(
+                    CspWeakness::WildcardHost,
+                    format!("{} allows any host", directive.name),
+                ),
+                SourceExpression::Host { host, .. } if host.starts_with('*') => lint(
+                    CspWeakness::WildcardHost,
+                    format!("{} allows the wildcard host {}", directive.name, source),
+                ),
+                SourceExpression::Scheme(scheme) if scheme == "http" => lint(
+                    CspWeakness::HttpSource,
+                    format!("{} allows http:", directive.name),
+                ),
+                SourceExpression::Host {
+                    scheme: Some(scheme),
+                    ..
+                } if scheme == "http" => lint(
+                    CspWeakness::HttpSource,
+                    format!("{} allows {} over plain HTTP", directive.name, source),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    if !policy.has_directive(&BaseUri) {
+        lint(CspWeakness::MissingBaseUri, "no base-uri".to_string());
+    }
+    let default_none = policy
+        .directive(&DefaultSrc)
+        .is_some_and(|d| d.sources == [SourceExpression::Keyword(Keyword::None)]);
+    if !policy.has_directive(&ObjectSrc) && !default_none {
+        lint(
+            CspWeakness::MissingObjectSrc,
+            "no object-src and default-src is not 'none'".to_string(),
+        );
+    }
+    if !policy.has_directive(&FrameAncestors) {
+        lint(
+            CspWeakness::MissingFrameAncestors,
+            "no frame-ancestors".to_string(),
+        );
+    }
+    if !policy.report_uris().is_empty() && policy.report_to().is_none() {
+        lint(
+            CspWeakness::ReportUriWithoutReportTo,
+            "report-uri without report-to".to_string(),
+        );
+    }
+    lints
+}
+
+/// A distinct policy string, how often it was seen and what is wrong with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintedPolicy<'a> {
+    pub policy: &'a str,
+    pub seen: usize,
+    pub lints: Vec<CspLint>,
+}
+
+/// The distinct policies seen in a set of checks, grouped by the origin of
+/// their loading principal, with how often each was seen.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CspCorpus {
+    by_origin: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl CspCorpus {
+    pub fn new() -> Self {
+        CspCorpus::default()
+    }
+
+    pub fn add(&mut self, check: &ContentSecurityCheck) {
+        let policies = match check.csp() {
+            Some(policies) if !policies.is_empty() => policies,
+            _ => return,
+        };
+        // Non-content principals like SystemPrincipal stand for themselves.
+        let origin = match check.loading_principal().origin() {
+            Some(origin) => serialize_origin(&origin),
+            None => check.loading_principal().to_string(),
+        };
+        let seen = self.by_origin.entry(origin).or_default();
+        for policy in policies {
+            *seen.entry(policy.clone()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: CspCorpus) {
+        for (origin, policies) in other.by_origin {
+            let seen = self.by_origin.entry(origin).or_default();
+            for (policy, count) in policies {
+                *seen.entry(policy).or_insert(0) += count;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_origin.is_empty()
+    }
+
+    /// Lints each distinct policy, grouped by origin.
+    pub fn lint(&self) -> Vec<(&str, Vec<LintedPolicy<'_>>)> {
+        self.by_origin
+            .iter()
+            .map(|(origin, policies)| {
+                let linted = policies
+                    .iter()
+                    .map(|(text, seen)| LintedPolicy {
+                        policy: text,
+                        seen: *seen,
+                        lints: lint_policy(&Policy::parse(text)),
+                    })
+                    .collect();
+                (origin.as_str(), linted)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests_csplint {
+    use super::{lint_policy, CspCorpus, CspWeakness};
+    use crate::parsing::csp::Policy;
+    use crate::parsing::tests::fixtures;
+
+    fn weaknesses(policy: &str) -> Vec<CspWeakness> {
+        lint_policy(&Policy::parse(policy))
+            .into_iter()
+            .map(|l| l.weakness)
+            .collect()
+    }
+
+    #[test]
+    fn fixture_policy() {
+        let check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        assert_eq!(
+            weaknesses(&check.csp().unwrap()[0]),
+            vec![
+                CspWeakness::UnsafeInline,
+                CspWeakness::DataScripts,
+                CspWeakness::MissingBaseUri,
+                CspWeakness::MissingObjectSrc,
+                CspWeakness::MissingFrameAncestors,
+                CspWeakness::ReportUriWithoutReportTo,
+            ]
+        );
+
+        let mut corpus = CspCorpus::new();
+        corpus.add(&check);
+        corpus.add(&check);
+        let report = corpus.lint();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].0, "https://www.raspberrypi.org");
+        assert_eq!(report[0].1[0].seen, 2);
+    }
+
+    #[test]
+    fn strict_policy() {
+        assert_eq!(
+            weaknesses(
+                "default-src 'none'; script-src 'nonce-abc' 'unsafe-inline' 'strict-dynamic'; \
+                 base-uri 'none'; frame-ancestors 'self'; report-uri /r; report-to csp"
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(
+            weaknesses(
+                "img-src * http://cdn.example.com; connect-src http:; object-src 'none'; \
+                 base-uri 'self'; frame-ancestors 'none'"
+            ),
+            vec![
+                CspWeakness::WildcardHost,
+                CspWeakness::HttpSource,
+                CspWeakness::HttpSource
+            ]
+        );
+        assert_eq!(
+            weaknesses(
+                "img-src *.example.com https://*; object-src 'none'; \
+                 base-uri 'self'; frame-ancestors 'none'"
+            ),
+            vec![CspWeakness::WildcardHost, CspWeakness::WildcardHost]
+        );
+    }
+}
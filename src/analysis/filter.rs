@@ -0,0 +1,680 @@
+//! A small expression language to pick checks by their fields, e.g.
+//!
+//! ```text
+//! loading_principal == SystemPrincipal && channel_uri ~ "^data:"
+//!     && external_type in [TYPE_SCRIPT, TYPE_STYLESHEET]
+//! ```
+//!
+//! Comparisons are `==`, `!=`, `^=` (prefix), `~` (regex search) and
+//! `in [..]`, on fields holding text, plus `contains` for `security_flags`.
+//! Boolean fields can stand on their own. Tests combine with `&&`, `||`,
+//! `!` and parentheses. Values are bare words or double-quoted strings, in
+//! which only `\"` and `\\` are escapes. `contains` rejects bare words that
+//! are not a known `SEC_*` flag; quote a name to match a flag this build does
+//! not know.
+
+use crate::parsing::checktypes::ContentSecurityCheck;
+use crate::parsing::policytypes::nsContentPolicyType;
+use crate::parsing::securityflags::{SecurityFlag, SecurityFlags};
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+use strum::VariantNames;
+use strum_macros::{EnumString, EnumVariantNames};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterError {
+    pub input: String,
+    /// Byte offset into `input` where parsing gave up.
+    pub position: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "can not parse filter {:?} at {}: {}",
+            self.input, self.position, self.reason
+        )
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// The fields of a `ContentSecurityCheck` a filter can look at.
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Field {
+    Source,
+    ProcessType,
+    Pid,
+    Thread,
+    ChannelUri,
+    HttpMethod,
+    LoadingPrincipal,
+    TriggeringPrincipal,
+    PrincipalToInherit,
+    /// The principals of all redirects, any of which may match.
+    RedirectChain,
+    InternalType,
+    ExternalType,
+    Category,
+    UpgradeInsecureRequests,
+    InitialSecurityChecksDone,
+    AllowDeprecatedSystemRequests,
+    /// The logged policies, any of which may match.
+    Csp,
+    SecurityFlags,
+}
+
+impl Field {
+    fn is_bool(self) -> bool {
+        matches!(
+            self,
+            Field::UpgradeInsecureRequests
+                | Field::InitialSecurityChecksDone
+                | Field::AllowDeprecatedSystemRequests
+        )
+    }
+
+    fn is_type(self) -> bool {
+        self == Field::InternalType || self == Field::ExternalType
+    }
+
+    /// The field's values as text. Empty for absent optional fields.
+    fn texts(self, check: &ContentSecurityCheck) -> Vec<String> {
+        let one = |text: String| vec![text];
+        match self {
+            Field::Source => check.source().map(String::from).into_iter().collect(),
+            Field::ProcessType => one(format!("{:?}", check.process_type())),
+            Field::Pid => check.pid().map(|pid| pid.to_string()).into_iter().collect(),
+            Field::Thread => check.thread().map(String::from).into_iter().collect(),
+            Field::ChannelUri => one(check.channel_uri().to_string()),
+            Field::HttpMethod => check.http_method().map(String::from).into_iter().collect(),
+            Field::LoadingPrincipal => one(check.loading_principal().to_string()),
+            Field::TriggeringPrincipal => one(check.triggering_principal().to_string()),
+            Field::PrincipalToInherit => one(check.principal_to_inherit().to_string()),
+            Field::RedirectChain => check
+                .redirect_chain()
+                .unwrap_or_default()
+                .iter()
+                .map(|hop| hop.principal.to_string())
+                .collect(),
+            Field::InternalType => one(check.internal_content_policy_type().to_string()),
+            Field::ExternalType => one(check.external_content_policy_type().to_string()),
+            Field::Category => one(check.internal_content_policy_type().category().to_string()),
+            Field::UpgradeInsecureRequests => one(check.upgrade_insecure_requests().to_string()),
+            Field::InitialSecurityChecksDone => {
+                one(check.initial_security_checks_done().to_string())
+            }
+            Field::AllowDeprecatedSystemRequests => {
+                one(check.allow_deprecated_system_requests().to_string())
+            }
+            Field::Csp => check.csp().unwrap_or_default().to_vec(),
+            Field::SecurityFlags => check
+                .security_flags()
+                .logged()
+                .map(|flag| flag.to_string())
+                .chain(check.security_flags().unknown().iter().cloned())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Equals(String),
+    Prefix(String),
+    Regex(Regex),
+    In(Vec<String>),
+    /// All of these flags are set.
+    Contains(Vec<FlagName>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FlagName {
+    Known(SecurityFlag),
+    /// A quoted name that is no `SecurityFlag`, matched against the unknown
+    /// flags of a check.
+    Unknown(String),
+}
+
+impl Test {
+    fn matches(&self, texts: &[String]) -> bool {
+        match self {
+            Test::Equals(value) => texts.iter().any(|text| text == value),
+            Test::Prefix(prefix) => texts.iter().any(|text| text.starts_with(prefix.as_str())),
+            Test::Regex(regex) => texts.iter().any(|text| regex.is_match(text)),
+            Test::In(values) => texts.iter().any(|text| values.contains(text)),
+            Test::Contains(_) => false,
+        }
+    }
+
+    fn contains_flags(names: &[FlagName], flags: &SecurityFlags) -> bool {
+        names.iter().all(|name| match name {
+            FlagName::Known(flag) => flags.contains(*flag),
+            FlagName::Unknown(name) => flags.unknown().contains(name),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Test(Field, Test),
+}
+
+impl Expr {
+    fn matches(&self, check: &ContentSecurityCheck) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(check) && b.matches(check),
+            Expr::Or(a, b) => a.matches(check) || b.matches(check),
+            Expr::Not(e) => !e.matches(check),
+            Expr::Test(_, Test::Contains(names)) => {
+                Test::contains_flags(names, check.security_flags())
+            }
+            Expr::Test(field, test) => test.matches(&field.texts(check)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Not,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Prefix,
+    Match,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Str(text) => write!(f, "{:?}", text),
+            Token::LParen => f.write_str("("),
+            Token::RParen => f.write_str(")"),
+            Token::LBracket => f.write_str("["),
+            Token::RBracket => f.write_str("]"),
+            Token::Comma => f.write_str(","),
+            Token::Not => f.write_str("!"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::Eq => f.write_str("=="),
+            Token::Ne => f.write_str("!="),
+            Token::Prefix => f.write_str("^="),
+            Token::Match => f.write_str("~"),
+        }
+    }
+}
+
+const PUNCTUATION: &str = "()[],!=&|^~\"";
+
+/// Splits `input` into tokens, each with its byte offset.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, (usize, String)> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let two = input.get(start..start + 2).unwrap_or_default();
+        let token = match (c, two) {
+            (_, "&&") => Token::And,
+            (_, "||") => Token::Or,
+            (_, "==") => Token::Eq,
+            (_, "!=") => Token::Ne,
+            (_, "^=") => Token::Prefix,
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('[', _) => Token::LBracket,
+            (']', _) => Token::RBracket,
+            (',', _) => Token::Comma,
+            ('!', _) => Token::Not,
+            ('~', _) => Token::Match,
+            ('"', _) => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        // Other backslashes are kept, for regular expressions.
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) if c == '"' || c == '\\' => text.push(c),
+                            Some((_, c)) => {
+                                text.push('\\');
+                                text.push(c);
+                            }
+                            None => return Err((start, "unterminated string".into())),
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => return Err((start, "unterminated string".into())),
+                    }
+                }
+                tokens.push((start, Token::Str(text)));
+                continue;
+            }
+            _ if PUNCTUATION.contains(c) => {
+                return Err((start, format!("unexpected {:?}", c)));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_whitespace() || PUNCTUATION.contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push((start, Token::Word(word)));
+                continue;
+            }
+        };
+        for _ in 0..token.to_string().len() {
+            chars.next();
+        }
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, reason: String) -> Result<T, FilterError> {
+        let position = match self.tokens.get(self.next) {
+            Some((position, _)) => *position,
+            None => self.input.len(),
+        };
+        Err(FilterError {
+            input: self.input.to_string(),
+            position,
+            reason,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), FilterError> {
+        if self.eat(token) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(found) => self.error(format!("expected {}, found {}", token, found)),
+            None => self.error(format!("expected {}", token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::LParen) {
+            let expr = self.or()?;
+            self.expect(&Token::RParen)?;
+            return Ok(expr);
+        }
+        self.test()
+    }
+
+    fn value(&mut self) -> Result<String, FilterError> {
+        match self.peek().cloned() {
+            Some(Token::Word(text)) | Some(Token::Str(text)) => {
+                self.next += 1;
+                Ok(text)
+            }
+            Some(found) => self.error(format!("expected a value, found {}", found)),
+            None => self.error("expected a value".into()),
+        }
+    }
+
+    /// A security flag. Bare words must name a known flag, quoted ones may
+    /// not.
+    fn flag(&mut self) -> Result<FlagName, FilterError> {
+        let quoted = match self.peek() {
+            Some(Token::Str(_)) => true,
+            Some(Token::Word(_)) => false,
+            Some(found) => return self.error(format!("expected a flag, found {}", found)),
+            None => return self.error("expected a flag".into()),
+        };
+        let name = self.value()?;
+        match SecurityFlag::from_str(&name) {
+            Ok(flag) => Ok(FlagName::Known(flag)),
+            Err(_) if quoted => Ok(FlagName::Unknown(name)),
+            Err(_) => {
+                self.next -= 1;
+                self.error(format!(
+                    "unknown security flag {}, quote it to match it anyway",
+                    name
+                ))
+            }
+        }
+    }
+
+    /// A single value or a bracketed, comma separated list of them.
+    fn values(&mut self) -> Result<Vec<String>, FilterError> {
+        self.list(Self::value)
+    }
+
+    /// A single `item` or a bracketed, comma separated list of them.
+    fn list<T>(
+        &mut self,
+        item: fn(&mut Self) -> Result<T, FilterError>,
+    ) -> Result<Vec<T>, FilterError> {
+        if !self.eat(&Token::LBracket) {
+            return Ok(vec![item(self)?]);
+        }
+        let mut values = vec![];
+        if self.eat(&Token::RBracket) {
+            return Ok(values);
+        }
+        loop {
+            values.push(item(self)?);
+            if self.eat(&Token::RBracket) {
+                return Ok(values);
+            }
+            self.expect(&Token::Comma)?;
+        }
+    }
+
+    fn test(&mut self) -> Result<Expr, FilterError> {
+        let name = match self.peek().cloned() {
+            Some(Token::Word(name)) => name,
+            Some(found) => return self.error(format!("expected a field, found {}", found)),
+            None => return self.error("expected a field".into()),
+        };
+        let field = match Field::from_str(&name) {
+            Ok(field) => field,
+            Err(_) => {
+                return self.error(format!(
+                    "unknown field {:?}, expected one of {}",
+                    name,
+                    Field::VARIANTS.join(", ")
+                ))
+            }
+        };
+        self.next += 1;
+        let operator = match self.peek() {
+            Some(Token::Eq) | Some(Token::Ne) | Some(Token::Prefix) | Some(Token::Match) => {
+                self.tokens[self.next].1.clone()
+            }
+            Some(Token::Word(word)) if word == "in" || word == "contains" => {
+                self.tokens[self.next].1.clone()
+            }
+            // Boolean fields test for `true` on their own.
+            _ if field.is_bool() => return Ok(Expr::Test(field, Test::Equals("true".into()))),
+            Some(found) => {
+                return self.error(format!(
+                    "expected an operator after {}, found {}",
+                    name, found
+                ))
+            }
+            None => return self.error(format!("expected an operator after {}", name)),
+        };
+        let is_contains = operator == Token::Word("contains".into());
+        if (field == Field::SecurityFlags) != is_contains {
+            return self.error(format!("{} can not be used with {}", operator, name));
+        }
+        self.next += 1;
+        let normalize = |value: String| match nsContentPolicyType::parse(&value) {
+            Some(t) if field.is_type() => t.to_string(),
+            _ => value,
+        };
+        let test = match &operator {
+            Token::Eq | Token::Ne => Test::Equals(normalize(self.value()?)),
+            Token::Prefix => Test::Prefix(self.value()?),
+            Token::Match => {
+                let pattern = self.value()?;
+                match Regex::new(&pattern) {
+                    Ok(regex) => Test::Regex(regex),
+                    Err(e) => {
+                        self.next -= 1;
+                        return self.error(e.to_string());
+                    }
+                }
+            }
+            Token::Word(word) if word == "in" => {
+                if self.peek() != Some(&Token::LBracket) {
+                    return self.error("expected [ after in".into());
+                }
+                Test::In(self.values()?.into_iter().map(normalize).collect())
+            }
+            _ => Test::Contains(self.list(Self::flag)?),
+        };
+        let expr = Expr::Test(field, test);
+        Ok(if operator == Token::Ne {
+            Expr::Not(Box::new(expr))
+        } else {
+            expr
+        })
+    }
+}
+
+/// A parsed filter expression, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    text: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn matches(&self, check: &ContentSecurityCheck) -> bool {
+        self.expr.matches(check)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(input).map_err(|(position, reason)| FilterError {
+            input: input.to_string(),
+            position,
+            reason,
+        })?;
+        let mut parser = Parser {
+            input,
+            tokens,
+            next: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(found) = parser.peek() {
+            return parser.error(format!("unexpected {}", found));
+        }
+        Ok(Filter {
+            text: input.to_string(),
+            expr,
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests_filter {
+    use super::Filter;
+    use crate::analysis::rules::{Rule, SystemDataScript};
+    use crate::parsing::checktypes::ContentSecurityCheck;
+    use crate::parsing::policytypes::nsContentPolicyType;
+    use crate::parsing::principal::Principal;
+    use crate::parsing::securityflags::SecurityFlags;
+    use crate::parsing::tests::fixtures;
+
+    fn matches(filter: &str, check: &ContentSecurityCheck) -> bool {
+        filter.parse::<Filter>().unwrap().matches(check)
+    }
+
+    #[test]
+    fn fields_and_operators() {
+        let check = fixtures::parse(fixtures::SAMPLE_BLOCK);
+        assert!(matches("loading_principal == SystemPrincipal", &check));
+        assert!(matches("principal_to_inherit == nullptr", &check));
+        assert!(matches(
+            r#"channel_uri ^= "https://incoming.telemetry.mozilla.org/""#,
+            &check
+        ));
+        assert!(matches(r#"channel_uri ~ "/bhr/Firefox/\d+""#, &check));
+        assert!(matches(r#"channel_uri ~ "\\?v=4$""#, &check));
+        assert!(matches(r#"channel_uri != "\"""#, &check));
+        assert!(matches("http_method in [GET, POST]", &check));
+        assert!(matches("external_type == TYPE_XMLHTTPREQUEST", &check));
+        // Types may be given by number, as in the IDL.
+        assert!(matches("internal_type == 33", &check));
+        assert!(matches("category == fetch", &check));
+        assert!(!matches("upgrade_insecure_requests", &check));
+        assert!(matches("!upgrade_insecure_requests", &check));
+        assert!(matches("security_flags contains SEC_COOKIES_OMIT", &check));
+        assert!(!matches(
+            "security_flags contains [SEC_COOKIES_OMIT, SEC_ALLOW_CHROME]",
+            &check
+        ));
+        assert!(!matches("csp ~ unsafe", &check));
+
+        let check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        assert!(matches("csp ~ \"'unsafe-eval'\"", &check));
+        assert!(matches(
+            "redirect_chain ^= \"https://www.raspberrypi.org/?\"",
+            &check
+        ));
+        assert!(matches("security_flags contains SEC_ALLOW_CHROME", &check));
+        assert!(matches(
+            "(http_method == POST || upgrade_insecure_requests) && thread != Main",
+            &check
+        ));
+    }
+
+    #[test]
+    fn quoted_unknown_flags() {
+        let mut check = fixtures::parse(fixtures::SAMPLE_BLOCK);
+        check.security_flags = SecurityFlags::from_logged(&["SEC_FROM_THE_FUTURE"]);
+        assert!(matches(
+            "security_flags contains \"SEC_FROM_THE_FUTURE\"",
+            &check
+        ));
+        assert!(!matches("security_flags contains \"SEC_OTHER\"", &check));
+        // Quoting a known flag changes nothing.
+        check.security_flags = SecurityFlags::from_logged(&["SEC_ALLOW_CHROME"]);
+        assert!(matches(
+            "security_flags contains [\"SEC_ALLOW_CHROME\"]",
+            &check
+        ));
+    }
+
+    #[test]
+    fn system_data_script_as_filter() {
+        let filter: Filter = "loading_principal == SystemPrincipal \
+            && channel_uri ~ \"^data:\" \
+            && !(channel_uri ^= \"data:text/css;extension=style;\") \
+            && external_type in [TYPE_SCRIPT, TYPE_STYLESHEET]"
+            .parse()
+            .unwrap();
+        let mut check = fixtures::parse(fixtures::SAMPLE_BLOCK);
+        let cases = [
+            ("data:text/javascript,1", nsContentPolicyType::TYPE_SCRIPT),
+            ("data:text/css,a{}", nsContentPolicyType::TYPE_STYLESHEET),
+            (
+                "data:text/css;extension=style;,a{}",
+                nsContentPolicyType::TYPE_STYLESHEET,
+            ),
+            ("data:image/png,", nsContentPolicyType::TYPE_IMAGE),
+            ("https://example.com/", nsContentPolicyType::TYPE_SCRIPT),
+        ];
+        for principal in &[Principal::SystemPrincipal, Principal::NullPrincipal] {
+            for (uri, external) in &cases {
+                check.loading_principal = principal.clone();
+                check.channel_uri = uri.to_string();
+                check.external_content_policy_type = (*external).into();
+                assert_eq!(
+                    filter.matches(&check),
+                    SystemDataScript.check(&check).is_some(),
+                    "{} {} {}",
+                    principal,
+                    uri,
+                    external
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = "channel_url == x".parse::<Filter>().unwrap_err();
+        assert_eq!(error.position, 0);
+        assert!(error.reason.starts_with("unknown field \"channel_url\""));
+
+        let error = "channel_uri ~ \"(\"".parse::<Filter>().unwrap_err();
+        assert_eq!(error.position, 14);
+
+        let error = "security_flags contains [SEC_ALLOW_CHROME, SEC_ALOW_CHROME]"
+            .parse::<Filter>()
+            .unwrap_err();
+        assert_eq!(error.position, 43);
+        assert!(error
+            .reason
+            .starts_with("unknown security flag SEC_ALOW_CHROME"));
+        for bad in &[
+            "",
+            "channel_uri",
+            "channel_uri ==",
+            "channel_uri contains x",
+            "security_flags == SEC_ALLOW_CHROME",
+            "security_flags contains SEC_ALOW_CHROME",
+            "security_flags contains [SEC_ALLOW_CHROME, SEC_ALOW_CHROME]",
+            "external_type in TYPE_SCRIPT",
+            "(upgrade_insecure_requests",
+            "upgrade_insecure_requests)",
+            "channel_uri == \"open",
+            "channel_uri = x",
+        ] {
+            assert!(bad.parse::<Filter>().is_err(), "{:?}", bad);
+        }
+    }
+}
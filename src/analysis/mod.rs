@@ -0,0 +1,10 @@
+//! Checks that look at parsed `ContentSecurityCheck`s and point out
+//! things worth a closer look.
+
+pub mod csp;
+pub mod csplint;
+pub mod filter;
+pub mod policytypes;
+pub mod redirects;
+pub mod rules;
+pub mod securityflags;
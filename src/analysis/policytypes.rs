@@ -0,0 +1,71 @@
+use crate::analysis::rules::{Finding, Rule, Severity};
+use crate::parsing::checktypes::ContentSecurityCheck;
+
+/// A check whose logged external content policy type is not what Gecko maps
+/// its internal type to. That is either a Gecko bug or a garbled log.
+/// Checks with a type we do not know can not be compared.
+pub struct PolicyTypeMismatch;
+
+impl Rule for PolicyTypeMismatch {
+    fn id(&self) -> &'static str {
+        "policy-type-mismatch"
+    }
+
+    fn group(&self) -> &'static str {
+        "types"
+    }
+
+    fn description(&self) -> &'static str {
+        "external content policy types that do not match the internal one"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, check: &ContentSecurityCheck) -> Option<Finding> {
+        let internal = check.internal_content_policy_type();
+        let external = check.external_content_policy_type();
+        if internal.known().is_none() || external.known().is_none() {
+            return None;
+        }
+        let expected = internal.to_external();
+        if &expected == external {
+            return None;
+        }
+        Some(Finding::new(
+            self,
+            format!(
+                "external type {} does not match internal type {}, expected {}",
+                external, internal, expected
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests_policy_type_mismatch {
+    use super::PolicyTypeMismatch;
+    use crate::analysis::rules::Rule;
+    use crate::parsing::policytypes::{nsContentPolicyType, ContentPolicyType};
+    use crate::parsing::tests::fixtures;
+
+    #[test]
+    fn consistent_fixtures() {
+        for block in &[fixtures::SAMPLE_BLOCK, fixtures::REDIRECT_CSP_BLOCK] {
+            let check = fixtures::parse(block);
+            assert_eq!(PolicyTypeMismatch.check(&check), None);
+        }
+    }
+
+    #[test]
+    fn mismatch() {
+        let mut check = fixtures::parse(fixtures::SAMPLE_BLOCK);
+        check.external_content_policy_type = nsContentPolicyType::TYPE_IMAGE.into();
+        let finding = PolicyTypeMismatch.check(&check).unwrap();
+        assert!(finding.message.ends_with("expected TYPE_XMLHTTPREQUEST"));
+
+        check.external_content_policy_type = ContentPolicyType::from_logged("TYPE_NEW");
+        assert_eq!(PolicyTypeMismatch.check(&check), None);
+    }
+}
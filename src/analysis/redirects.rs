@@ -0,0 +1,190 @@
+use crate::analysis::rules::{Finding, Rule, Severity};
+use crate::parsing::checktypes::ContentSecurityCheck;
+use std::fmt;
+use url::Url;
+
+/// Gecko's default for `network.http.redirection-limit`.
+pub const DEFAULT_MAX_REDIRECTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RedirectRule {
+    /// A hop went from HTTPS to plain HTTP.
+    Downgrade,
+    /// The chain passes through, or ends in, a `data:` or `blob:` URL.
+    DataOrBlob,
+    /// The chain has more than this many hops.
+    TooLong(usize),
+}
+
+impl fmt::Display for RedirectRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+fn is_data_or_blob(url: &Url) -> bool {
+    url.scheme() == "data" || url.scheme() == "blob"
+}
+
+impl Rule for RedirectRule {
+    fn id(&self) -> &'static str {
+        match self {
+            RedirectRule::Downgrade => "redirect-downgrade",
+            RedirectRule::DataOrBlob => "redirect-data-or-blob",
+            RedirectRule::TooLong(_) => "redirect-too-long",
+        }
+    }
+
+    fn group(&self) -> &'static str {
+        "redirects"
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            RedirectRule::Downgrade => "redirects from HTTPS to plain HTTP",
+            RedirectRule::DataOrBlob => "redirects through or to data: and blob: URLs",
+            RedirectRule::TooLong(_) => {
+                "redirect chains with more hops than the configured maximum"
+            }
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            RedirectRule::TooLong(_) => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+
+    fn check(&self, check: &ContentSecurityCheck) -> Option<Finding> {
+        let hops = match check.redirect_chain() {
+            Some(hops) if !hops.is_empty() => hops,
+            _ => return None,
+        };
+        let details: Vec<String> = match self {
+            RedirectRule::Downgrade => hops
+                .iter()
+                .enumerate()
+                .filter(|(_, hop)| hop.downgrades)
+                .map(|(i, hop)| format!("hop {} ({}) redirects to plain HTTP", i, hop.principal))
+                .collect(),
+            RedirectRule::DataOrBlob => {
+                let mut details: Vec<String> = hops
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, hop)| {
+                        let url = hop.url.as_ref().filter(|url| is_data_or_blob(url))?;
+                        Some(format!("hop {} is a {}: URL", i, url.scheme()))
+                    })
+                    .collect();
+                if Url::parse(check.channel_uri())
+                    .ok()
+                    .as_ref()
+                    .is_some_and(is_data_or_blob)
+                {
+                    details.push("redirects end in a data: or blob: URL".to_string());
+                }
+                details
+            }
+            RedirectRule::TooLong(max) if hops.len() > *max => {
+                vec![format!("{} redirects, more than {}", hops.len(), max)]
+            }
+            RedirectRule::TooLong(_) => vec![],
+        };
+        Finding::of_all(self, &details)
+    }
+}
+
+#[cfg(test)]
+mod tests_redirect_rules {
+    use super::{RedirectRule, DEFAULT_MAX_REDIRECTS};
+    use crate::parsing::redirects::RedirectHop;
+    use crate::parsing::tests::fixtures;
+    use crate::parsing::CheckStream;
+
+    fn rules_for(chain: &[&str], channel_uri: &str, max: usize) -> Vec<RedirectRule> {
+        let mut check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        let entries: Vec<String> = chain.iter().map(|s| s.to_string()).collect();
+        check.channel_uri = channel_uri.to_string();
+        check.redirect_chain = Some(RedirectHop::chain(&entries, channel_uri));
+        fixtures::rules_finding(&redirect_rules(max), &check)
+    }
+
+    fn redirect_rules(max: usize) -> [RedirectRule; 3] {
+        [
+            RedirectRule::Downgrade,
+            RedirectRule::DataOrBlob,
+            RedirectRule::TooLong(max),
+        ]
+    }
+
+    #[test]
+    fn fixture_is_fine() {
+        let check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        assert_eq!(check.redirect_chain().map(|c| c.len()), Some(1));
+        assert_eq!(
+            fixtures::rules_finding(&redirect_rules(DEFAULT_MAX_REDIRECTS), &check),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn downgrade_in_logged_chain() {
+        let log = "[Parent 1: Main Thread]: D/CSMLog \n\
+            #DebugDoContentSecurityCheck Begin\n\
+            [Parent 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            [Parent 1: Main Thread]: V/CSMLog   - channelURI: http://example.com/c\n\
+            [Parent 1: Main Thread]: V/CSMLog   - redirectChain:\n\
+            [Parent 1: Main Thread]: V/CSMLog     -: https://example.com/a\n\
+            [Parent 1: Main Thread]: V/CSMLog     -: https://example.com/b\n\
+            [Parent 1: Main Thread]: V/CSMLog   - CSP:\n\
+            [Parent 1: Main Thread]: D/CSMLog \n\
+            #DebugDoContentSecurityCheck End\n";
+        let checks: Vec<_> = CheckStream::new(log.as_bytes())
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(
+            fixtures::rules_finding(&redirect_rules(DEFAULT_MAX_REDIRECTS), &checks[0]),
+            vec![RedirectRule::Downgrade]
+        );
+    }
+
+    #[test]
+    fn downgrade() {
+        assert_eq!(
+            rules_for(&["https://example.com/"], "http://example.com/", 20),
+            vec![RedirectRule::Downgrade]
+        );
+    }
+
+    #[test]
+    fn data_and_blob() {
+        assert_eq!(
+            rules_for(
+                &["https://example.com/", "blob:https://example.com/abc"],
+                "https://example.com/x",
+                20
+            ),
+            vec![RedirectRule::DataOrBlob]
+        );
+        assert_eq!(
+            rules_for(&["https://example.com/"], "data:text/html,hi", 20),
+            vec![RedirectRule::DataOrBlob]
+        );
+    }
+
+    #[test]
+    fn too_long() {
+        let chain = [
+            "https://a.example/",
+            "https://b.example/",
+            "https://c.example/",
+        ];
+        assert_eq!(
+            rules_for(&chain, "https://d.example/", 2),
+            vec![RedirectRule::TooLong(2)]
+        );
+        assert_eq!(rules_for(&chain, "https://d.example/", 3), vec![]);
+    }
+}
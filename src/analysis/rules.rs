@@ -0,0 +1,255 @@
+use crate::analysis::csp::CspBlocked;
+use crate::analysis::policytypes::PolicyTypeMismatch;
+use crate::analysis::redirects::{RedirectRule, DEFAULT_MAX_REDIRECTS};
+use crate::analysis::securityflags::FlagRule;
+use crate::parsing::checktypes::ContentSecurityCheck;
+use crate::parsing::policytypes::nsContentPolicyType;
+use crate::parsing::principal::Principal;
+use std::fmt;
+use strum_macros::{EnumString, IntoStaticStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.into())
+    }
+}
+
+/// Something a `Rule` found about a single check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    /// A finding of `rule`, at the rule's severity.
+    pub fn new<R: Rule>(rule: &R, message: String) -> Self {
+        Finding {
+            rule: rule.id(),
+            severity: rule.severity(),
+            message,
+        }
+    }
+
+    /// A finding of `rule` listing all `details`, if there are any.
+    pub fn of_all<R: Rule>(rule: &R, details: &[String]) -> Option<Self> {
+        if details.is_empty() {
+            None
+        } else {
+            Some(Finding::new(rule, details.join("; ")))
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.rule, self.severity, self.message)
+    }
+}
+
+/// An audit run against every check that was scanned.
+pub trait Rule: Send + Sync {
+    /// Short kebab-case name, used to select the rule on the command line.
+    fn id(&self) -> &'static str;
+    /// Related rules share a group, which selects all of them at once.
+    fn group(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn severity(&self) -> Severity;
+    fn check(&self, check: &ContentSecurityCheck) -> Option<Finding>;
+}
+
+/// Scripts and stylesheets the system principal loads from `data:` URLs.
+/// Stylesheets injected by extensions (`extension=style`) are expected and
+/// skipped.
+pub struct SystemDataScript;
+
+impl Rule for SystemDataScript {
+    fn id(&self) -> &'static str {
+        "system-data-script"
+    }
+
+    fn group(&self) -> &'static str {
+        "data"
+    }
+
+    fn description(&self) -> &'static str {
+        "scripts and stylesheets loaded by the system principal from data: URLs"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, check: &ContentSecurityCheck) -> Option<Finding> {
+        let uri = check.channel_uri();
+        if !uri.starts_with("data:") || uri.starts_with("data:text/css;extension=style;") {
+            return None;
+        }
+        if *check.loading_principal() != Principal::SystemPrincipal {
+            return None;
+        }
+        let external = check.external_content_policy_type();
+        if *external != nsContentPolicyType::TYPE_SCRIPT
+            && *external != nsContentPolicyType::TYPE_STYLESHEET
+        {
+            return None;
+        }
+        Some(Finding::new(
+            self,
+            format!("system principal loads {} from a data: URL", external),
+        ))
+    }
+}
+
+/// Settings of the built-in rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleOptions {
+    pub max_redirects: usize,
+}
+
+impl Default for RuleOptions {
+    fn default() -> Self {
+        RuleOptions {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+}
+
+/// The rules run unless others are picked.
+pub const DEFAULT_RULES: [&str; 1] = ["system-data-script"];
+
+/// All rules that come with silver-chainsaw, in the order they run.
+pub fn builtin_rules(options: &RuleOptions) -> Vec<Box<dyn Rule>> {
+    let mut rules: Vec<Box<dyn Rule>> = vec![Box::new(SystemDataScript)];
+    for rule in &FlagRule::ALL {
+        rules.push(Box::new(*rule));
+    }
+    rules.push(Box::new(PolicyTypeMismatch));
+    for rule in &[
+        RedirectRule::Downgrade,
+        RedirectRule::DataOrBlob,
+        RedirectRule::TooLong(options.max_redirects),
+    ] {
+        rules.push(Box::new(*rule));
+    }
+    rules.push(Box::new(CspBlocked));
+    rules
+}
+
+/// The rules picked for a scan.
+pub struct RuleSet {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl RuleSet {
+    /// The built-in rules named in `only`, or the `DEFAULT_RULES` if it is
+    /// empty, minus those in `skip`. Both name rules by id or group. Fails
+    /// with the first name that is neither.
+    pub fn select(options: &RuleOptions, only: &[String], skip: &[String]) -> Result<Self, String> {
+        let rules = builtin_rules(options);
+        let defaults: Vec<String> = DEFAULT_RULES.iter().map(|id| id.to_string()).collect();
+        let only = if only.is_empty() { &defaults[..] } else { only };
+        let names = |rule: &dyn Rule, name: &str| rule.id() == name || rule.group() == name;
+        if let Some(unknown) = only
+            .iter()
+            .chain(skip)
+            .find(|name| !rules.iter().any(|rule| names(rule.as_ref(), name)))
+        {
+            return Err(unknown.clone());
+        }
+        let rules = rules
+            .into_iter()
+            .filter(|rule| only.iter().any(|name| names(rule.as_ref(), name)))
+            .filter(|rule| !skip.iter().any(|name| names(rule.as_ref(), name)))
+            .collect();
+        Ok(RuleSet { rules })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.rules.iter().map(|rule| rule.as_ref())
+    }
+
+    /// Runs every rule against `check`.
+    pub fn check(&self, check: &ContentSecurityCheck) -> Vec<Finding> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.check(check))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests_rules {
+    use super::{RuleOptions, RuleSet, Severity};
+    use crate::parsing::policytypes::nsContentPolicyType;
+    use crate::parsing::principal::Principal;
+    use crate::parsing::tests::fixtures;
+
+    #[test]
+    fn system_data_script() {
+        let rules = RuleSet::select(&RuleOptions::default(), &[], &[]).unwrap();
+        let mut check = fixtures::parse(fixtures::SAMPLE_BLOCK);
+        assert_eq!(rules.check(&check), vec![]);
+
+        check.channel_uri = "data:text/javascript,alert(1)".to_string();
+        check.external_content_policy_type = nsContentPolicyType::TYPE_SCRIPT.into();
+        let findings = rules.check(&check);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, "system-data-script");
+        assert_eq!(findings[0].severity, Severity::Warning);
+
+        check.channel_uri = "data:text/css;extension=style;charset=utf-8,a{}".to_string();
+        check.external_content_policy_type = nsContentPolicyType::TYPE_STYLESHEET.into();
+        assert_eq!(rules.check(&check), vec![]);
+
+        check.channel_uri = "data:text/css,a{}".to_string();
+        check.loading_principal = Principal::NullPrincipal;
+        assert_eq!(rules.check(&check), vec![]);
+    }
+
+    #[test]
+    fn select() {
+        let options = RuleOptions::default();
+        let defaults = RuleSet::select(&options, &[], &[]).unwrap();
+        let ids: Vec<_> = defaults.iter().map(|rule| rule.id()).collect();
+        assert_eq!(ids, vec!["system-data-script"]);
+
+        let by_group = vec!["redirects".to_string()];
+        let ids: Vec<_> = RuleSet::select(&options, &by_group, &[])
+            .unwrap()
+            .iter()
+            .map(|rule| rule.id())
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                "redirect-downgrade",
+                "redirect-data-or-blob",
+                "redirect-too-long"
+            ]
+        );
+        let skip = vec!["redirect-too-long".to_string()];
+        assert_eq!(
+            RuleSet::select(&options, &by_group, &skip)
+                .unwrap()
+                .iter()
+                .count(),
+            2
+        );
+
+        let only = vec!["no-such-rule".to_string()];
+        assert_eq!(
+            RuleSet::select(&options, &only, &[]).err(),
+            Some("no-such-rule".to_string())
+        );
+    }
+}
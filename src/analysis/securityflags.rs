@@ -0,0 +1,245 @@
+use crate::analysis::rules::{Finding, Rule, Severity};
+use crate::parsing::checktypes::ContentSecurityCheck;
+use crate::parsing::principal::Principal;
+use crate::parsing::securityflags::{SecurityFlag, SecurityFlags, SEC_MODE_MASK};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The combinations of security flags Gecko considers invalid or that
+/// should make us suspicious about the code creating the channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FlagRule {
+    /// The logged cookie flags are not what Gecko logs for any single
+    /// cookie policy. Gecko logs every constant `c` with `flags & c != 0`,
+    /// so `SEC_COOKIES_OMIT` shows up as INCLUDE, SAME_ORIGIN and OMIT.
+    ConflictingCookiePolicies,
+    /// None of the five `SEC_REQUIRE_*`/`SEC_ALLOW_*` security modes is set.
+    MissingSecurityMode,
+    /// More than one of the mutually exclusive security modes is set.
+    MultipleSecurityModes,
+    /// `SEC_ALLOW_CHROME` on a load whose loading principal is web content.
+    ChromeForContent,
+    /// `SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER` only has an effect
+    /// together with `SEC_FORCE_INHERIT_PRINCIPAL`.
+    OverruleOwnerWithoutInherit,
+    /// A flag name this version does not know about.
+    UnknownFlag,
+}
+
+impl FlagRule {
+    pub const ALL: [FlagRule; 6] = [
+        FlagRule::ConflictingCookiePolicies,
+        FlagRule::MissingSecurityMode,
+        FlagRule::MultipleSecurityModes,
+        FlagRule::ChromeForContent,
+        FlagRule::OverruleOwnerWithoutInherit,
+        FlagRule::UnknownFlag,
+    ];
+}
+
+impl fmt::Display for FlagRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+const COOKIE_POLICIES: [SecurityFlag; 3] = [
+    SecurityFlag::SEC_COOKIES_INCLUDE,
+    SecurityFlag::SEC_COOKIES_SAME_ORIGIN,
+    SecurityFlag::SEC_COOKIES_OMIT,
+];
+
+/// Whether the logged cookie flags could stem from a single cookie policy,
+/// either as Gecko logs it or spelled out by its name alone.
+fn cookie_flags_consistent(flags: &SecurityFlags) -> bool {
+    let logged: BTreeSet<SecurityFlag> = flags.logged().filter(|f| f.is_cookie_policy()).collect();
+    logged.is_empty()
+        || COOKIE_POLICIES.iter().any(|policy| {
+            let as_logged: BTreeSet<SecurityFlag> = COOKIE_POLICIES
+                .iter()
+                .copied()
+                .filter(|f| policy.value() & f.value() != 0)
+                .collect();
+            logged == as_logged || (logged.len() == 1 && logged.contains(policy))
+        })
+}
+
+impl Rule for FlagRule {
+    fn id(&self) -> &'static str {
+        match self {
+            FlagRule::ConflictingCookiePolicies => "conflicting-cookie-policies",
+            FlagRule::MissingSecurityMode => "missing-security-mode",
+            FlagRule::MultipleSecurityModes => "multiple-security-modes",
+            FlagRule::ChromeForContent => "chrome-for-content",
+            FlagRule::OverruleOwnerWithoutInherit => "overrule-owner-without-inherit",
+            FlagRule::UnknownFlag => "unknown-flag",
+        }
+    }
+
+    fn group(&self) -> &'static str {
+        "flags"
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            FlagRule::ConflictingCookiePolicies => "cookie flags of more than one cookie policy",
+            FlagRule::MissingSecurityMode => "no security mode flag",
+            FlagRule::MultipleSecurityModes => "more than one security mode flag",
+            FlagRule::ChromeForContent => "SEC_ALLOW_CHROME for a content loading principal",
+            FlagRule::OverruleOwnerWithoutInherit => {
+                "SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER without SEC_FORCE_INHERIT_PRINCIPAL"
+            }
+            FlagRule::UnknownFlag => "security flags this version does not know",
+        }
+    }
+
+    /// Combinations Gecko considers invalid are errors, the rest warnings.
+    fn severity(&self) -> Severity {
+        match self {
+            FlagRule::ConflictingCookiePolicies | FlagRule::MultipleSecurityModes => {
+                Severity::Error
+            }
+            FlagRule::UnknownFlag => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+
+    fn check(&self, check: &ContentSecurityCheck) -> Option<Finding> {
+        let flags = check.security_flags();
+        let details: Vec<String> = match self {
+            FlagRule::ConflictingCookiePolicies if !cookie_flags_consistent(flags) => {
+                let logged: Vec<String> = flags
+                    .logged()
+                    .filter(|f| f.is_cookie_policy())
+                    .map(|f| f.to_string())
+                    .collect();
+                vec![format!(
+                    "cookie flags {} name more than one policy",
+                    logged.join(", ")
+                )]
+            }
+            FlagRule::MissingSecurityMode if flags.bits() & SEC_MODE_MASK == 0 => {
+                vec!["no security mode flag set".to_string()]
+            }
+            FlagRule::MultipleSecurityModes if (flags.bits() & SEC_MODE_MASK).count_ones() > 1 => {
+                let modes: Vec<String> = flags
+                    .iter()
+                    .filter(|f| f.value() & SEC_MODE_MASK != 0)
+                    .map(|f| f.to_string())
+                    .collect();
+                vec![format!(
+                    "security modes {} are mutually exclusive",
+                    modes.join(", ")
+                )]
+            }
+            FlagRule::ChromeForContent if flags.contains(SecurityFlag::SEC_ALLOW_CHROME) => {
+                match check.loading_principal() {
+                    Principal::ContentPrincipal(p) => vec![format!(
+                        "SEC_ALLOW_CHROME with content loading principal {}",
+                        p
+                    )],
+                    _ => vec![],
+                }
+            }
+            FlagRule::OverruleOwnerWithoutInherit
+                if flags.contains(SecurityFlag::SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER)
+                    && !flags.contains(SecurityFlag::SEC_FORCE_INHERIT_PRINCIPAL) =>
+            {
+                vec![
+                    "SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER without SEC_FORCE_INHERIT_PRINCIPAL"
+                        .to_string(),
+                ]
+            }
+            FlagRule::UnknownFlag => flags
+                .unknown()
+                .iter()
+                .map(|name| format!("unknown flag {}", name))
+                .collect(),
+            _ => vec![],
+        };
+        Finding::of_all(self, &details)
+    }
+}
+
+#[cfg(test)]
+mod tests_flag_rules {
+    use super::FlagRule;
+    use crate::parsing::securityflags::SecurityFlags;
+    use crate::parsing::tests::fixtures;
+
+    fn rules_for(block: &str, flags: &[&str]) -> Vec<FlagRule> {
+        let mut check = fixtures::parse(block);
+        check.security_flags = SecurityFlags::from_logged(flags);
+        fixtures::rules_finding(&FlagRule::ALL, &check)
+    }
+
+    // INCLUDE+SAME_ORIGIN+OMIT is how Gecko logs `SEC_COOKIES_OMIT`, see
+    // `FlagRule::ConflictingCookiePolicies`.
+    #[test]
+    fn logged_cookie_omit_is_fine() {
+        let check = fixtures::parse(fixtures::SAMPLE_BLOCK);
+        assert_eq!(fixtures::rules_finding(&FlagRule::ALL, &check), vec![]);
+    }
+
+    #[test]
+    fn conflicting_cookie_policies() {
+        let rules = rules_for(
+            fixtures::SAMPLE_BLOCK,
+            &[
+                "SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL",
+                "SEC_COOKIES_INCLUDE",
+                "SEC_COOKIES_SAME_ORIGIN",
+            ],
+        );
+        assert_eq!(rules, vec![FlagRule::ConflictingCookiePolicies]);
+    }
+
+    #[test]
+    fn security_modes() {
+        let rules = rules_for(fixtures::SAMPLE_BLOCK, &["SEC_COOKIES_OMIT"]);
+        assert_eq!(rules, vec![FlagRule::MissingSecurityMode]);
+        let rules = rules_for(
+            fixtures::SAMPLE_BLOCK,
+            &[
+                "SEC_REQUIRE_CORS_INHERITS_SEC_CONTEXT",
+                "SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL",
+            ],
+        );
+        assert_eq!(rules, vec![FlagRule::MultipleSecurityModes]);
+    }
+
+    #[test]
+    fn chrome_for_content() {
+        // The redirect fixture has a content loading principal.
+        let check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        assert_eq!(
+            fixtures::rules_finding(&FlagRule::ALL, &check),
+            vec![FlagRule::ChromeForContent]
+        );
+        // ... while the sample is loaded by the system principal.
+        let rules = rules_for(
+            fixtures::SAMPLE_BLOCK,
+            &[
+                "SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL",
+                "SEC_ALLOW_CHROME",
+            ],
+        );
+        assert_eq!(rules, vec![]);
+    }
+
+    #[test]
+    fn overrule_owner_and_unknown() {
+        let rules = rules_for(
+            fixtures::SAMPLE_BLOCK,
+            &[
+                "SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL",
+                "SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER",
+                "SEC_FROM_THE_FUTURE",
+            ],
+        );
+        assert_eq!(
+            rules,
+            vec![FlagRule::OverruleOwnerWithoutInherit, FlagRule::UnknownFlag]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use flate2::read::MultiGzDecoder;
+use log::info;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use xz2::read::XzDecoder;
+
+/// Where a stream of MOZ_LOG lines comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputSource {
+    Stdin,
+    File(PathBuf),
+    /// Chunks of one logical log written with `MOZ_LOG=rotate:N`, by
+    /// rotation number, e.g. `log.txt.moz_log.0`, `log.txt.moz_log.1`.
+    Rotated {
+        name: PathBuf,
+        parts: Vec<PathBuf>,
+    },
+}
+
+impl InputSource {
+    /// Opens the source for reading, decompressing it on the fly if needed.
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        match self {
+            InputSource::Stdin => Ok(Box::new(BufReader::new(decompressed(io::stdin())?))),
+            InputSource::File(path) => {
+                Ok(Box::new(BufReader::new(decompressed(File::open(path)?)?)))
+            }
+            InputSource::Rotated { parts, .. } => {
+                // Chain the chunks so that blocks spanning a rotation survive.
+                let mut reader: Box<dyn Read> = Box::new(io::empty());
+                for part in parts {
+                    reader = Box::new(reader.chain(decompressed(File::open(part)?)?));
+                }
+                Ok(Box::new(BufReader::new(reader)))
+            }
+        }
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Looks at the first bytes of `reader` and wraps it in a gzip, zstd or xz
+/// decoder if they carry the respective magic number. Anything else is
+/// passed through as is.
+pub(crate) fn decompressed<R: Read + 'static>(mut reader: R) -> io::Result<Box<dyn Read>> {
+    let mut magic = Vec::with_capacity(XZ_MAGIC.len());
+    // A pipe may hand out fewer bytes than asked for; keep reading until we
+    // have enough to tell or the input ends.
+    while magic.len() < XZ_MAGIC.len() {
+        let mut byte = [0u8; 1];
+        match reader.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => magic.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    let reader = io::Cursor::new(magic.clone()).chain(reader);
+    Ok(if magic.starts_with(GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::stream::read::Decoder::new(reader)?)
+    } else if magic.starts_with(XZ_MAGIC) {
+        Box::new(XzDecoder::new_multi_decoder(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputSource::Stdin => write!(f, "<stdin>"),
+            InputSource::File(path) => write!(f, "{}", path.display()),
+            InputSource::Rotated { name, .. } => write!(f, "{}.*", name.display()),
+        }
+    }
+}
+
+/// Recursively finds all MOZ_LOG files below `dir`: the parent's
+/// `log.txt.moz_log`, per-child `log.txt-child.1234.moz_log` and their
+/// rotated `.moz_log.N` chunks, each possibly compressed with gzip, zstd or
+/// xz. Chunks of the same log become one source. Fails if a file is there
+/// both plain and compressed, e.g. `log.txt.moz_log` and `log.txt.moz_log.gz`.
+pub fn scan_dir(dir: &Path) -> io::Result<Vec<InputSource>> {
+    let is_moz_log = Regex::new(r"^(.+\.moz_log)(?:\.(\d+))?(?:\.(?:gz|zst|xz))?$").unwrap();
+    // (logical name) -> [(rotation number, mtime, path)]
+    let mut logs: BTreeMap<PathBuf, Vec<(Option<u32>, SystemTime, PathBuf)>> = BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            } else if !file_type.is_file() {
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(caps) = is_moz_log.captures(&file_name) {
+                let name = path.with_file_name(&caps[1]);
+                let number = caps.get(2).and_then(|n| n.as_str().parse().ok());
+                let mtime = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                logs.entry(name).or_default().push((number, mtime, path));
+            } else {
+                info!("Skipping ineligible file {:?}", path);
+            }
+        }
+    }
+
+    let mut sources = vec![];
+    for (name, mut parts) in logs {
+        // By rotation number, the mtime only breaks ties.
+        parts.sort();
+        if let Some(pair) = parts.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "both {} and {} exist, remove one of them",
+                    pair[0].2.display(),
+                    pair[1].2.display()
+                ),
+            ));
+        }
+        sources.push(if parts.len() == 1 && parts[0].0.is_none() {
+            InputSource::File(parts.remove(0).2)
+        } else {
+            InputSource::Rotated {
+                name,
+                parts: parts.into_iter().map(|(_, _, path)| path).collect(),
+            }
+        });
+    }
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod tests_decompressed {
+    use super::decompressed;
+    use crate::parsing::CheckStream;
+    use std::io::{BufReader, Read, Write};
+
+    const LOG: &str = include_str!("parsing/tests/block-and-incomplete.txt");
+
+    fn checks_in(compressed: Vec<u8>) -> usize {
+        let reader = decompressed(std::io::Cursor::new(compressed)).unwrap();
+        CheckStream::new(BufReader::new(reader))
+            .filter(|r| r.is_ok())
+            .count()
+    }
+
+    #[test]
+    fn plain_text_passes_through() {
+        let mut text = String::new();
+        decompressed(LOG.as_bytes())
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, LOG);
+    }
+
+    #[test]
+    fn short_input_passes_through() {
+        let mut text = String::new();
+        decompressed(&b"\x1f"[..])
+            .unwrap()
+            .read_to_end(&mut vec![])
+            .unwrap();
+        decompressed(&b""[..])
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(LOG.as_bytes()).unwrap();
+        assert_eq!(checks_in(encoder.finish().unwrap()), 2);
+    }
+
+    #[test]
+    fn zstd() {
+        assert_eq!(checks_in(zstd::encode_all(LOG.as_bytes(), 3).unwrap()), 2);
+    }
+
+    #[test]
+    fn xz() {
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(LOG.as_bytes()).unwrap();
+        assert_eq!(checks_in(encoder.finish().unwrap()), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_scan_dir {
+    use super::{scan_dir, InputSource};
+    use crate::parsing::CheckStream;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    const FIRST_HALF: &str = "[Parent 1: Main Thread]: D/CSMLog \n\
+        #DebugDoContentSecurityCheck Begin\n\
+        [Parent 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n";
+    const SECOND_HALF: &str =
+        "[Parent 1: Main Thread]: V/CSMLog   - channelURI: https://example.com/\n\
+        [Parent 1: Main Thread]: D/CSMLog \n\
+        #DebugDoContentSecurityCheck End\n";
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("silver-chainsaw-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        dir
+    }
+
+    fn write(path: &PathBuf, content: &str, age: u64) {
+        fs::write(path, content).unwrap();
+        let mtime = SystemTime::now() - Duration::from_secs(age);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn stitches_rotated_chunks_in_number_order() {
+        let dir = scratch_dir("rotated");
+        // The number wins over the mtime.
+        write(&dir.join("log.txt.moz_log.2"), FIRST_HALF, 30);
+        write(&dir.join("log.txt.moz_log.10"), SECOND_HALF, 60);
+        write(&dir.join("sub/log.txt-child.42.moz_log.gz"), "", 0);
+        write(&dir.join("notes.txt"), "", 0);
+
+        let sources = scan_dir(&dir).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                InputSource::Rotated {
+                    name: dir.join("log.txt.moz_log"),
+                    parts: vec![
+                        dir.join("log.txt.moz_log.2"),
+                        dir.join("log.txt.moz_log.10")
+                    ],
+                },
+                InputSource::File(dir.join("sub/log.txt-child.42.moz_log.gz")),
+            ]
+        );
+
+        let checks: Vec<_> = CheckStream::new(sources[0].open().unwrap())
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].channel_uri(), "https://example.com/");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_plain_and_compressed_copies() {
+        let dir = scratch_dir("copies");
+        write(&dir.join("log.txt.moz_log"), FIRST_HALF, 0);
+        write(&dir.join("log.txt.moz_log.gz"), "", 0);
+        let error = scan_dir(&dir).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("log.txt.moz_log.gz"));
+
+        fs::remove_file(dir.join("log.txt.moz_log.gz")).unwrap();
+        write(&dir.join("log.txt.moz_log.1"), SECOND_HALF, 0);
+        write(&dir.join("log.txt.moz_log.1.zst"), "", 0);
+        assert!(scan_dir(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+//! Parses the `CSMLog` output of Firefox's content security manager
+//! (`MOZ_LOG=CSMLog:5`) into `ContentSecurityCheck`s.
+//!
+//! ```no_run
+//! use silver_chainsaw::CheckStream;
+//! use std::io::BufReader;
+//!
+//! let log = std::fs::File::open("log.txt.moz_log").unwrap();
+//! for check in CheckStream::new(BufReader::new(log)).flatten() {
+//!     println!("{}", check.channel_uri());
+//! }
+//! ```
+
+#[macro_use]
+extern crate serde_derive;
+
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate url;
+
+pub(crate) mod analysis;
+pub(crate) mod input;
+pub(crate) mod parsing;
+pub(crate) mod scan;
+
+pub use crate::analysis::csp::{evaluate_check, Evaluation, Verdict};
+pub use crate::analysis::csplint::{lint_policy, CspCorpus, CspLint, CspWeakness};
+pub use crate::analysis::filter::{Filter, FilterError};
+pub use crate::analysis::redirects::DEFAULT_MAX_REDIRECTS;
+pub use crate::analysis::rules::{
+    builtin_rules, Finding, Rule, RuleOptions, RuleSet, Severity, DEFAULT_RULES,
+};
+pub use crate::input::{scan_dir, InputSource};
+pub use crate::parsing::checktypes::ContentSecurityCheck;
+pub use crate::parsing::policytypes::{nsContentPolicyType, ContentPolicyType, PolicyCategory};
+pub use crate::parsing::principal::{
+    ContentPrincipal, OriginAttributes, Principal, PrincipalError,
+};
+pub use crate::parsing::redirects::RedirectHop;
+pub use crate::parsing::securityflags::{SecurityFlag, SecurityFlags};
+pub use crate::parsing::{
+    parse_contentpolicytype, parse_log, parse_timestamp, CheckStream, LogLevel, ParseError,
+    ProcessType,
+};
+pub use crate::scan::{scan, ScanOptions};
@@ -1,30 +1,129 @@
-#[macro_use]
-extern crate serde_derive;
-
 extern crate getopts;
 extern crate regex;
-extern crate serde;
-extern crate serde_json;
-extern crate url;
 
-use crate::parsing::checktypes::ContentSecurityCheck;
-use crate::parsing::parse_log;
-use crate::parsing::policytypes::nsContentPolicyType;
-use crate::parsing::principal::Principal;
+use silver_chainsaw::{
+    builtin_rules, nsContentPolicyType, parse_timestamp, scan, scan_dir, InputSource,
+    PolicyCategory, RuleOptions, RuleSet, ScanOptions, DEFAULT_MAX_REDIRECTS, DEFAULT_RULES,
+};
 
-use getopts::Options;
-use log::info;
+use chrono::{DateTime, Utc};
+use getopts::{Matches, Options};
 use std::env;
 use std::io;
-use std::io::BufReader;
-
-mod parsing;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum::VariantNames;
 
 fn print_usage(program: &str, opts: &Options) {
-    let brief = format!("Usage: {} FILE [options]", program);
+    let brief = format!("Usage: {} [options]", program);
     print!("{}", opts.usage(&brief));
 }
 
+/// Reports a bad command line on stderr, followed by the usage, and exits.
+fn usage_error(program: &str, opts: &Options, message: &str) -> ! {
+    let brief = format!("Usage: {} [options]", program);
+    eprintln!("{}: {}\n\n{}", program, message, opts.usage(&brief));
+    std::process::exit(2);
+}
+
+/// The `--check-*` options, each running a group of rules on top of the
+/// others.
+const CHECK_GROUPS: [(&str, &str); 4] = [
+    ("check-flags", "flags"),
+    ("check-types", "types"),
+    ("check-redirects", "redirects"),
+    ("check-csp", "csp"),
+];
+
+/// The rules and groups picked with `--rule` and the `--check-*` options.
+/// Empty if the default rules should run.
+fn picked_rules(matches: &Matches) -> Vec<String> {
+    let mut picked = matches.opt_strs("rule");
+    let checks: Vec<String> = CHECK_GROUPS
+        .iter()
+        .filter(|(option, _)| matches.opt_present(option))
+        .map(|(_, group)| group.to_string())
+        .collect();
+    if !checks.is_empty() && picked.is_empty() {
+        picked = DEFAULT_RULES.iter().map(|id| id.to_string()).collect();
+    }
+    picked.extend(checks);
+    picked
+}
+
+fn time_opt(matches: &Matches, name: &str) -> Result<Option<DateTime<Utc>>, String> {
+    match matches.opt_str(name) {
+        Some(text) => match parse_timestamp(&text) {
+            Some(t) => Ok(Some(t)),
+            None => Err(format!("--{}: can not parse time {:?}", name, text)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parses the number given with `--name`, if any.
+fn number_opt(matches: &Matches, name: &str) -> Result<Option<usize>, String> {
+    match matches.opt_str(name) {
+        Some(n) => match n.parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(e) => Err(format!("--{}: {} in {:?}", name, e, n)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// What to scan for, as given on the command line.
+fn scan_options(matches: &Matches) -> Result<ScanOptions, String> {
+    let rule_options = RuleOptions {
+        max_redirects: number_opt(matches, "max-redirects")?.unwrap_or(DEFAULT_MAX_REDIRECTS),
+    };
+    let rules = RuleSet::select(
+        &rule_options,
+        &picked_rules(matches),
+        &matches.opt_strs("skip-rule"),
+    )
+    .map_err(|name| format!("unknown rule or group {:?}, see --list-rules", name))?;
+    let mut external_types = vec![];
+    for name in matches.opt_strs("type") {
+        match nsContentPolicyType::parse(&name) {
+            Some(t) => external_types.push(t.to_external()),
+            None => return Err(format!("--type: unknown content policy type {:?}", name)),
+        }
+    }
+    let mut categories = vec![];
+    for name in matches.opt_strs("category") {
+        match PolicyCategory::from_str(&name) {
+            Ok(c) => categories.push(c),
+            Err(_) => {
+                return Err(format!(
+                    "--category: expected one of {}, got {:?}",
+                    PolicyCategory::VARIANTS.join(", "),
+                    name
+                ))
+            }
+        }
+    }
+    let filter = match matches.opt_str("filter") {
+        Some(text) => Some(text.parse().map_err(|e| format!("--filter: {}", e))?),
+        None => None,
+    };
+    Ok(ScanOptions {
+        since: time_opt(matches, "since")?,
+        until: time_opt(matches, "until")?,
+        strict: matches.opt_present("strict"),
+        lint_csp: matches.opt_present("lint-csp"),
+        external_types,
+        categories,
+        filter,
+        rules,
+        sort_by_time: matches.opt_present("sort-by-time"),
+        verbose: matches.opt_present("v"),
+        by_category: matches.opt_present("by-category"),
+        // 0 lets rayon pick one per core.
+        jobs: number_opt(matches, "jobs")?.unwrap_or(0),
+    })
+}
+
 fn main() -> io::Result<()> {
     env_logger::init();
     // arg parsing
@@ -33,75 +132,162 @@ fn main() -> io::Result<()> {
     let mut opts = Options::new();
     opts.optflag("v", "verbose", "give more verbose output");
     opts.optflag("h", "help", "print usage info");
+    opts.optmulti(
+        "",
+        "rule",
+        &format!(
+            "only run this rule, or the rules of this group (default: {}, see --list-rules)",
+            DEFAULT_RULES.join(", ")
+        ),
+        "ID|GROUP",
+    );
+    opts.optmulti(
+        "",
+        "skip-rule",
+        "do not run this rule, or the rules of this group",
+        "ID|GROUP",
+    );
+    opts.optflag("", "list-rules", "list the built-in rules and exit");
+    opts.optflag(
+        "",
+        "strict",
+        "fail on the first block that can not be parsed",
+    );
+    opts.optflag(
+        "",
+        "check-flags",
+        "report invalid or suspicious security flag combinations",
+    );
+    opts.optflag(
+        "",
+        "check-types",
+        "report checks whose external content policy type does not match the internal one",
+    );
+    opts.optflag("", "check-csp", "report loads the logged CSP would block");
+    opts.optflag(
+        "",
+        "lint-csp",
+        "lint all distinct CSPs seen, grouped by the origin of the loading principal",
+    );
+    opts.optflag(
+        "",
+        "check-redirects",
+        "report redirect chains that downgrade to HTTP or pass through data: or blob:",
+    );
+    opts.optopt(
+        "",
+        "max-redirects",
+        &format!(
+            "with --check-redirects, also report chains longer than this (default: {})",
+            DEFAULT_MAX_REDIRECTS
+        ),
+        "N",
+    );
+    opts.optmulti(
+        "",
+        "type",
+        "only consider checks of this external content policy type, \
+         including all internal types mapping to it",
+        "TYPE_SCRIPT",
+    );
+    opts.optmulti(
+        "",
+        "category",
+        "only consider checks of this category of content policy types",
+        "script|style|image|media|document|fetch|font|other",
+    );
+    opts.optopt(
+        "",
+        "filter",
+        "only consider checks matching EXPR, and print all of them, e.g. \
+         'channel_uri ~ \"^data:\" && external_type in [TYPE_SCRIPT, TYPE_STYLESHEET]'",
+        "EXPR",
+    );
+    opts.optflag(
+        "",
+        "by-category",
+        "break the number of scanned checks down by content policy type category",
+    );
+    opts.optopt(
+        "",
+        "since",
+        "only consider checks logged at or after this time (UTC)",
+        "\"YYYY-MM-DD HH:MM:SS\"",
+    );
+    opts.optopt(
+        "",
+        "until",
+        "only consider checks logged before this time (UTC)",
+        "\"YYYY-MM-DD HH:MM:SS\"",
+    );
+    opts.optflag(
+        "",
+        "sort-by-time",
+        "print results ordered by their log timestamp instead of as they are found",
+    );
+    opts.optopt(
+        "j",
+        "jobs",
+        "parse this many files in parallel (default: one per core)",
+        "N",
+    );
     opts.optopt(
         "d",
         "dir",
-        "read files matching moz_log from this directory",
+        "read all (rotated and per-process) moz_log files below this directory",
         "DIRECTORY",
     );
-    opts.optmulti("i", "input", "read from these files", "INFILE");
+    opts.optmulti(
+        "i",
+        "input",
+        "read from these files (default: stdin, unless -d is given)",
+        "INFILE",
+    );
     //let mut verbosity_lvl = 0;
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(e) => panic!("{}", e.to_string()),
+        Err(e) => usage_error(&program, &opts, &e.to_string()),
     };
     if matches.opt_present("h") {
         print_usage(&program, &opts);
         return Ok(());
     }
-    let mut happyblocks: Vec<ContentSecurityCheck> = vec![];
+    if matches.opt_present("list-rules") {
+        for rule in builtin_rules(&RuleOptions::default()) {
+            println!(
+                "{:<32} {:<10} {:<8} {}",
+                rule.id(),
+                rule.group(),
+                rule.severity(),
+                rule.description()
+            );
+        }
+        return Ok(());
+    }
+    let scan_opts = match scan_options(&matches) {
+        Ok(scan_opts) => scan_opts,
+        Err(message) => usage_error(&program, &opts, &message),
+    };
+    let mut sources: Vec<InputSource> = vec![];
+
+    for inputname in matches.opt_strs("i") {
+        sources.push(InputSource::File(PathBuf::from(inputname)));
+    }
 
     let diropt = matches.opt_str("d");
 
+    // Neither -i nor -d: expect MOZ_LOG output to be piped in.
+    if sources.is_empty() && diropt.is_none() {
+        sources.push(InputSource::Stdin);
+    }
+
     if let Some(dirname) = diropt {
         println!("Scanning {}", dirname);
-        for entry in std::fs::read_dir(dirname)? {
-            if let Ok(entry) = entry {
-                if let Ok(file_type) = entry.file_type() {
-                    // Now let's show our entry's file type!
-                    if file_type.is_file() {
-                        let file_name = entry.path();
-                        if file_name.to_str().unwrap().ends_with(".moz_log") {
-                            let h = std::fs::File::open(file_name).unwrap();
-                            let bufreader = BufReader::new(h);
-                            if let Ok(mut moar_checks) = parse_log(Box::new(bufreader)) {
-                                happyblocks.append(&mut moar_checks);
-                            }
-                        } else {
-                            info!("Skipping ineligible file {:?}", file_name);
-                        }
-                    }
-                }
-            }
-        }
+        sources.append(&mut scan_dir(Path::new(&dirname))?);
     }
 
-    // now comes the cool analysis, I guess
-    println!("happyblocks length: {}", happyblocks.len());
-    for c in happyblocks {
-        if c.channel_uri.starts_with("data:")
-            && !c.channel_uri.starts_with("data:text/css;extension=style;")
-            && c.loading_principal == Principal::SystemPrincipal
-            && (c.external_content_policy_type == nsContentPolicyType::TYPE_SCRIPT
-                || c.external_content_policy_type == nsContentPolicyType::TYPE_STYLESHEET)
-        {
-            println!("{:?}", c);
-        }
+    if !scan(&sources, &scan_opts)? {
+        std::process::exit(1);
     }
-    println!("that's all that were interesting.");
-    // TODO: parse every -i <file> and add to happyblocks
-    /*let input_file_names = matches.opt_strs("i");
-    let mut contents = String::new();
-
-    if input_file_names.is_empty() {
-        let mut inputhandle: Box<dyn io::Read> = Box::new(io::stdin());
-        inputhandle.read_to_string(&mut contents)?;
-    } else {
-        for inputname in input_file_names {
-            let mut file = File::open(inputname)?;
-            file.read_to_string(&mut contents)?;
-        }
-    }*/
-
     Ok(())
 }
@@ -1,68 +1,275 @@
 #![allow(clippy::upper_case_acronyms)]
-use crate::parsing::policytypes::nsContentPolicyType;
+use crate::parsing::csp::Policy;
+use crate::parsing::policytypes::ContentPolicyType;
 use crate::parsing::principal::Principal;
-use crate::parsing::ProcessType;
+use crate::parsing::redirects::RedirectHop;
+use crate::parsing::securityflags::SecurityFlags;
+use crate::parsing::{LogLevel, ProcessType};
+use chrono::{DateTime, Utc};
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+use strum::VariantNames;
+use strum_macros::EnumVariantNames;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[allow(non_snake_case)]
 pub(crate) struct WrappedCheck {
     pub(crate) doContentSecurityCheck: Vec<CheckLine>,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, EnumVariantNames)]
 #[allow(non_snake_case, non_camel_case_types)]
-pub enum CheckLine {
+pub(crate) enum CheckLine {
     channelURI(String),
     httpMethod(Option<String>), // only shown for http channels
     loadingPrincipal(Principal),
     triggeringPrincipal(Principal),
     principalToInherit(Principal),
+    #[serde(deserialize_with = "deserialize_redirect_chain")]
     redirectChain(Option<Vec<String>>),
-    internalContentPolicyType(nsContentPolicyType),
-    externalContentPolicyType(nsContentPolicyType),
+    internalContentPolicyType(ContentPolicyType),
+    externalContentPolicyType(ContentPolicyType),
     upgradeInsecureRequests(bool),
     initialSecurityChecksDone(bool),
     allowDeprecatedSystemRequests(bool),
     CSP(Option<Vec<String>>),
-    securityFlags(Vec<String>),
+    securityFlags(SecurityFlags),
+}
+
+/// Finds the first `- key: value` item in a block that is not a `CheckLine`.
+/// Only meant to explain why a block failed to deserialize.
+pub(crate) fn unknown_field(block: &str) -> Option<String> {
+    let value: serde_yaml::Value = serde_yaml::from_str(block).ok()?;
+    let items = value.get("doContentSecurityCheck")?.as_sequence()?;
+    items
+        .iter()
+        .filter_map(|item| item.as_mapping())
+        .flat_map(|mapping| mapping.iter())
+        .filter_map(|(key, _)| key.as_str())
+        .find(|key| !CheckLine::VARIANTS.contains(key))
+        .map(String::from)
+}
+
+/// Gecko logs the entries of a redirect chain as `-: <spec>` in the column of
+/// the `redirectChain` key, which YAML reads as more keys of the enclosing
+/// item. Indents them below `redirectChain` instead and joins the block.
+pub(crate) fn nest_redirect_chain(block: &[String]) -> String {
+    let mut chain_indent: Option<usize> = None;
+    let mut nested = Vec::with_capacity(block.len());
+    for line in block {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        if trimmed.starts_with("- redirectChain:") {
+            chain_indent = Some(indent);
+        } else if trimmed.starts_with("- ") {
+            chain_indent = None;
+        } else if let Some(key_indent) = chain_indent {
+            if trimmed.starts_with("-:") && indent <= key_indent + 2 {
+                nested.push(format!("{}{}", " ".repeat(key_indent + 4), trimmed));
+                continue;
+            }
+        }
+        nested.push(line.clone());
+    }
+    nested.join("\n")
+}
+
+/// Once nested, the `-: <spec>` entries of a redirect chain are a mapping
+/// with (possibly repeated) `-` keys rather than a list. Accept both forms.
+fn deserialize_redirect_chain<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct RedirectChainVisitor;
+
+    impl<'de> Visitor<'de> for RedirectChainVisitor {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a list of redirects")
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_any(self)
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut chain = vec![];
+            while let Some(entry) = seq.next_element::<String>()? {
+                chain.push(entry);
+            }
+            Ok(Some(chain))
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut chain = vec![];
+            while let Some((_, entry)) = map.next_entry::<String, String>()? {
+                chain.push(entry);
+            }
+            Ok(Some(chain))
+        }
+    }
+    deserializer.deserialize_any(RedirectChainVisitor)
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct ContentSecurityCheck {
+    #[serde(default)]
+    pub(crate) source: Option<String>, // file (or stdin) the check was read from
     pub(crate) process_type: ProcessType,
+    #[serde(default)]
+    pub(crate) pid: Option<u32>,
+    #[serde(default)]
+    pub(crate) thread: Option<String>,
+    #[serde(default)]
+    pub(crate) log_level: Option<LogLevel>, // most verbose level used within the block
+    #[serde(default)]
+    pub(crate) timestamp: Option<DateTime<Utc>>, // only if MOZ_LOG=timestamp was used
     pub(crate) channel_uri: String,
     pub(crate) http_method: Option<String>, // only shown for http channels
     pub(crate) loading_principal: Principal,
     pub(crate) triggering_principal: Principal,
     pub(crate) principal_to_inherit: Principal,
-    pub(crate) redirect_chain: Option<Vec<String>>,
-    pub(crate) internal_content_policy_type: nsContentPolicyType,
-    pub(crate) external_content_policy_type: nsContentPolicyType,
+    pub(crate) redirect_chain: Option<Vec<RedirectHop>>,
+    pub(crate) internal_content_policy_type: ContentPolicyType,
+    pub(crate) external_content_policy_type: ContentPolicyType,
     pub(crate) upgrade_insecure_requests: bool,
     pub(crate) initial_security_checks_done: bool,
     pub(crate) allow_deprecated_system_requests: bool,
     pub(crate) csp: Option<Vec<String>>, // key always present, might be empty value
-    pub(crate) security_flags: Vec<String>,
+    pub(crate) security_flags: SecurityFlags,
 }
+impl ContentSecurityCheck {
+    /// Name of the file (or `<stdin>`) the check was read from.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn process_type(&self) -> ProcessType {
+        self.process_type
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn thread(&self) -> Option<&str> {
+        self.thread.as_deref()
+    }
+
+    /// The most verbose level any line of the block was logged at.
+    pub fn log_level(&self) -> Option<LogLevel> {
+        self.log_level
+    }
+
+    /// Only present if the log was written with `MOZ_LOG=timestamp,...`.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.timestamp
+    }
+
+    pub fn channel_uri(&self) -> &str {
+        &self.channel_uri
+    }
+
+    /// Only logged for http channels.
+    pub fn http_method(&self) -> Option<&str> {
+        self.http_method.as_deref()
+    }
+
+    pub fn loading_principal(&self) -> &Principal {
+        &self.loading_principal
+    }
+
+    pub fn triggering_principal(&self) -> &Principal {
+        &self.triggering_principal
+    }
+
+    pub fn principal_to_inherit(&self) -> &Principal {
+        &self.principal_to_inherit
+    }
+
+    pub fn redirect_chain(&self) -> Option<&[RedirectHop]> {
+        self.redirect_chain.as_deref()
+    }
+
+    pub fn internal_content_policy_type(&self) -> &ContentPolicyType {
+        &self.internal_content_policy_type
+    }
+
+    pub fn external_content_policy_type(&self) -> &ContentPolicyType {
+        &self.external_content_policy_type
+    }
+
+    pub fn upgrade_insecure_requests(&self) -> bool {
+        self.upgrade_insecure_requests
+    }
+
+    pub fn initial_security_checks_done(&self) -> bool {
+        self.initial_security_checks_done
+    }
+
+    pub fn allow_deprecated_system_requests(&self) -> bool {
+        self.allow_deprecated_system_requests
+    }
+
+    pub fn csp(&self) -> Option<&[String]> {
+        self.csp.as_deref()
+    }
+
+    /// The logged CSP strings, parsed.
+    pub fn csp_policies(&self) -> Vec<Policy> {
+        self.csp()
+            .unwrap_or_default()
+            .iter()
+            .map(|policy| Policy::parse(policy))
+            .collect()
+    }
+
+    pub fn security_flags(&self) -> &SecurityFlags {
+        &self.security_flags
+    }
+}
+
 impl From<Vec<CheckLine>> for ContentSecurityCheck {
     fn from(lines: Vec<CheckLine>) -> Self {
         let mut channel_uri: String = "XX-MISSING_URL".to_string();
         let mut http_method: Option<String> = None;
         let mut loading_principal: Principal =
-            Principal::ContentPrincipal("xxx://missing-url".to_string());
+            Principal::ContentPrincipal("xxx://missing-url".parse().unwrap());
         let mut triggering_principal: Principal =
-            Principal::ContentPrincipal("xxx://missing-url".to_string());
+            Principal::ContentPrincipal("xxx://missing-url".parse().unwrap());
         let mut principal_to_inherit: Principal =
-            Principal::ContentPrincipal("xxx://missing-url".to_string());
+            Principal::ContentPrincipal("xxx://missing-url".parse().unwrap());
         let mut redirect_chain: Option<Vec<String>> = None;
-        let mut internal_content_policy_type: nsContentPolicyType =
-            nsContentPolicyType::TYPE_INVALID;
-        let mut external_content_policy_type: nsContentPolicyType =
-            nsContentPolicyType::TYPE_INVALID;
+        let mut internal_content_policy_type = ContentPolicyType::default();
+        let mut external_content_policy_type = ContentPolicyType::default();
         let mut upgrade_insecure_requests: bool = false;
         let mut initial_security_checks_done: bool = false;
         let mut allow_deprecated_system_requests: bool = false;
         let mut csp: Option<Vec<String>> = None;
-        let mut security_flags: Vec<String> = vec![];
+        let mut security_flags = SecurityFlags::default();
 
         for line in lines {
             match line {
@@ -83,8 +290,15 @@ impl From<Vec<CheckLine>> for ContentSecurityCheck {
                 CheckLine::securityFlags(sf) => security_flags = sf,
             }
         }
+        let redirect_chain =
+            redirect_chain.map(|entries| RedirectHop::chain(&entries, &channel_uri));
         ContentSecurityCheck {
+            source: None,
             process_type: ProcessType::Unknown,
+            pid: None,
+            thread: None,
+            log_level: None,
+            timestamp: None,
             channel_uri,
             http_method,
             loading_principal,
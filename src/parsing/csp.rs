@@ -0,0 +1,458 @@
+//! Content Security Policies as logged in the `CSP` field of a check.
+//!
+//! Gecko logs each policy of the loading document on its own, serialized
+//! as `directive value value; directive ...`.
+
+use std::fmt;
+use std::str::FromStr;
+use strum_macros::{EnumString, IntoStaticStr};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "kebab-case")]
+pub enum DirectiveName {
+    DefaultSrc,
+    ScriptSrc,
+    ScriptSrcElem,
+    ScriptSrcAttr,
+    StyleSrc,
+    StyleSrcElem,
+    StyleSrcAttr,
+    ImgSrc,
+    MediaSrc,
+    FontSrc,
+    ConnectSrc,
+    ObjectSrc,
+    FrameSrc,
+    ChildSrc,
+    WorkerSrc,
+    ManifestSrc,
+    WebrtcSrc,
+    BaseUri,
+    FormAction,
+    FrameAncestors,
+    NavigateTo,
+    Sandbox,
+    UpgradeInsecureRequests,
+    BlockAllMixedContent,
+    RequireTrustedTypesFor,
+    TrustedTypes,
+    ReportUri,
+    ReportTo,
+    /// A directive we do not know, lowercased.
+    #[strum(disabled)]
+    Unknown(String),
+}
+
+impl DirectiveName {
+    pub fn parse(name: &str) -> DirectiveName {
+        let name = name.to_ascii_lowercase();
+        DirectiveName::from_str(&name).unwrap_or(DirectiveName::Unknown(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            DirectiveName::Unknown(name) => name,
+            known => known.into(),
+        }
+    }
+
+    /// Whether the directive's value is a source list.
+    pub fn is_source_list(&self) -> bool {
+        let name = self.as_str();
+        name.ends_with("-src")
+            || name.ends_with("-src-elem")
+            || name.ends_with("-src-attr")
+            || matches!(
+                self,
+                DirectiveName::BaseUri
+                    | DirectiveName::FormAction
+                    | DirectiveName::FrameAncestors
+                    | DirectiveName::NavigateTo
+            )
+    }
+}
+
+impl fmt::Display for DirectiveName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, IntoStaticStr)]
+pub enum Keyword {
+    #[strum(serialize = "'self'")]
+    SelfOrigin,
+    #[strum(serialize = "'none'")]
+    None,
+    #[strum(serialize = "'unsafe-inline'")]
+    UnsafeInline,
+    #[strum(serialize = "'unsafe-eval'")]
+    UnsafeEval,
+    #[strum(serialize = "'unsafe-hashes'")]
+    UnsafeHashes,
+    #[strum(serialize = "'strict-dynamic'")]
+    StrictDynamic,
+    #[strum(serialize = "'report-sample'")]
+    ReportSample,
+    #[strum(serialize = "'wasm-unsafe-eval'")]
+    WasmUnsafeEval,
+    #[strum(serialize = "'unsafe-allow-redirects'")]
+    UnsafeAllowRedirects,
+    #[strum(serialize = "'inline-speculation-rules'")]
+    InlineSpeculationRules,
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.into())
+    }
+}
+
+/// One entry of a source list.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SourceExpression {
+    Keyword(Keyword),
+    Nonce(String),
+    Hash {
+        algorithm: String,
+        digest: String,
+    },
+    /// `https:`, stored without the colon and lowercased.
+    Scheme(String),
+    /// `*`, `example.com`, `https://*.example.com:*/path` and the like. The
+    /// host keeps a leading `*.` wildcard; a port of `*` is kept as such.
+    Host {
+        scheme: Option<String>,
+        host: String,
+        port: Option<String>,
+        path: Option<String>,
+    },
+    /// Anything else, kept as logged.
+    Other(String),
+}
+
+fn is_scheme(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+impl SourceExpression {
+    pub fn parse(token: &str) -> SourceExpression {
+        let lower = token.to_ascii_lowercase();
+        if lower.starts_with('\'') {
+            if let Ok(keyword) = Keyword::from_str(&lower) {
+                return SourceExpression::Keyword(keyword);
+            }
+            let quoted = match token[1..].strip_suffix('\'') {
+                Some(quoted) => quoted,
+                None => return SourceExpression::Other(token.to_string()),
+            };
+            // The prefixes are case-insensitive, the values are not. ASCII
+            // lowercasing keeps the offsets into `quoted` intact.
+            let quoted_lower = quoted.to_ascii_lowercase();
+            if quoted_lower.starts_with("nonce-") {
+                return SourceExpression::Nonce(quoted["nonce-".len()..].to_string());
+            }
+            for algorithm in &["sha256", "sha384", "sha512"] {
+                let prefix = format!("{}-", algorithm);
+                if quoted_lower.starts_with(&prefix) {
+                    return SourceExpression::Hash {
+                        algorithm: algorithm.to_string(),
+                        digest: quoted[prefix.len()..].to_string(),
+                    };
+                }
+            }
+            return SourceExpression::Other(token.to_string());
+        }
+        if let Some(scheme) = lower.strip_suffix(':') {
+            if is_scheme(scheme) {
+                return SourceExpression::Scheme(scheme.to_string());
+            }
+        }
+        let (scheme, rest) = match lower.find("://") {
+            Some(pos) if is_scheme(&lower[..pos]) => {
+                (Some(lower[..pos].to_string()), &token[pos + 3..])
+            }
+            Some(_) => return SourceExpression::Other(token.to_string()),
+            None => (None, token),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], Some(rest[pos..].to_string())),
+            None => (rest, None),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(pos) => (&authority[..pos], Some(authority[pos + 1..].to_string())),
+            None => (authority, None),
+        };
+        let labels = host.strip_prefix("*.").unwrap_or(host);
+        let valid_host = host == "*"
+            || (!labels.is_empty()
+                && labels
+                    .split('.')
+                    .all(|l| !l.is_empty() && l.chars().all(|c| c.is_alphanumeric() || c == '-')));
+        let valid_port = port
+            .as_deref()
+            .is_none_or(|p| p == "*" || (!p.is_empty() && p.chars().all(|c| c.is_ascii_digit())));
+        if !valid_host || !valid_port {
+            return SourceExpression::Other(token.to_string());
+        }
+        SourceExpression::Host {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        }
+    }
+}
+
+impl fmt::Display for SourceExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceExpression::Keyword(keyword) => write!(f, "{}", keyword),
+            SourceExpression::Nonce(nonce) => write!(f, "'nonce-{}'", nonce),
+            SourceExpression::Hash { algorithm, digest } => {
+                write!(f, "'{}-{}'", algorithm, digest)
+            }
+            SourceExpression::Scheme(scheme) => write!(f, "{}:", scheme),
+            SourceExpression::Host {
+                scheme,
+                host,
+                port,
+                path,
+            } => {
+                if let Some(scheme) = scheme {
+                    write!(f, "{}://", scheme)?;
+                }
+                write!(f, "{}", host)?;
+                if let Some(port) = port {
+                    write!(f, ":{}", port)?;
+                }
+                if let Some(path) = path {
+                    write!(f, "{}", path)?;
+                }
+                Ok(())
+            }
+            SourceExpression::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    pub name: DirectiveName,
+    /// The values as logged.
+    pub values: Vec<String>,
+    /// The parsed values, for directives that take a source list.
+    pub sources: Vec<SourceExpression>,
+}
+
+impl Directive {
+    pub fn has_keyword(&self, keyword: Keyword) -> bool {
+        self.sources.contains(&SourceExpression::Keyword(keyword))
+    }
+}
+
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for value in &self.values {
+            write!(f, " {}", value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    /// In the order they were logged. Like browsers, lookups only consider
+    /// the first of several directives with the same name.
+    pub directives: Vec<Directive>,
+    /// Gecko does not log whether a policy is report-only, so this is only
+    /// set for policies built with `parse_report_only`.
+    pub report_only: bool,
+}
+
+impl Policy {
+    /// Parses a serialized policy. Unknown directives are kept, empty ones
+    /// are skipped; this never fails.
+    pub fn parse(text: &str) -> Policy {
+        let directives = text
+            .split(';')
+            .filter_map(|directive| {
+                let mut tokens = directive.split_ascii_whitespace();
+                let name = DirectiveName::parse(tokens.next()?);
+                let values: Vec<String> = tokens.map(String::from).collect();
+                let sources = if name.is_source_list() {
+                    values.iter().map(|v| SourceExpression::parse(v)).collect()
+                } else {
+                    vec![]
+                };
+                Some(Directive {
+                    name,
+                    values,
+                    sources,
+                })
+            })
+            .collect();
+        Policy {
+            directives,
+            report_only: false,
+        }
+    }
+
+    pub fn parse_report_only(text: &str) -> Policy {
+        Policy {
+            report_only: true,
+            ..Policy::parse(text)
+        }
+    }
+
+    pub fn directive(&self, name: &DirectiveName) -> Option<&Directive> {
+        self.directives.iter().find(|d| &d.name == name)
+    }
+
+    pub fn has_directive(&self, name: &DirectiveName) -> bool {
+        self.directive(name).is_some()
+    }
+
+    /// Whether any directive lists `keyword`.
+    pub fn has_keyword(&self, keyword: Keyword) -> bool {
+        self.directives.iter().any(|d| d.has_keyword(keyword))
+    }
+
+    pub fn upgrade_insecure_requests(&self) -> bool {
+        self.has_directive(&DirectiveName::UpgradeInsecureRequests)
+    }
+
+    /// The URIs of all `report-uri` directives.
+    pub fn report_uris(&self) -> Vec<&str> {
+        self.directives
+            .iter()
+            .filter(|d| d.name == DirectiveName::ReportUri)
+            .flat_map(|d| d.values.iter().map(|v| v.as_str()))
+            .collect()
+    }
+
+    /// The group named by `report-to`, if any.
+    pub fn report_to(&self) -> Option<&str> {
+        self.directive(&DirectiveName::ReportTo)
+            .and_then(|d| d.values.first())
+            .map(|v| v.as_str())
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let directives: Vec<String> = self.directives.iter().map(|d| d.to_string()).collect();
+        write!(f, "{}", directives.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests_csp {
+    use super::{DirectiveName, Keyword, Policy, SourceExpression};
+    use crate::parsing::tests::fixtures;
+
+    #[test]
+    fn fixture_policy() {
+        let check = fixtures::parse(fixtures::REDIRECT_CSP_BLOCK);
+        let policies = check.csp_policies();
+        assert_eq!(policies.len(), 1);
+        let policy = &policies[0];
+        assert!(policy.upgrade_insecure_requests());
+        assert!(policy.has_keyword(Keyword::UnsafeEval));
+        assert!(!policy.has_directive(&DirectiveName::ObjectSrc));
+        assert!(!policy.report_only);
+        assert_eq!(
+            policy.report_uris(),
+            vec!["https://e4f0000014a954844abf8fe208613d8.report-uri.com/r/d/csp/enforce"]
+        );
+        assert_eq!(
+            policy.directive(&DirectiveName::ImgSrc).unwrap().sources,
+            vec![
+                SourceExpression::Scheme("https".to_string()),
+                SourceExpression::Keyword(Keyword::SelfOrigin),
+                SourceExpression::Scheme("blob".to_string()),
+                SourceExpression::Scheme("data".to_string()),
+            ]
+        );
+        assert_eq!(policy.to_string(), check.csp().unwrap()[0]);
+    }
+
+    #[test]
+    fn source_expressions() {
+        let parsed = |text| SourceExpression::parse(text);
+        assert_eq!(
+            parsed("'nonce-abc123'"),
+            SourceExpression::Nonce("abc123".to_string())
+        );
+        assert_eq!(
+            parsed("'sha256-AbC='"),
+            SourceExpression::Hash {
+                algorithm: "sha256".to_string(),
+                digest: "AbC=".to_string()
+            }
+        );
+        assert_eq!(
+            parsed("'NONCE-AbC123'"),
+            SourceExpression::Nonce("AbC123".to_string())
+        );
+        assert_eq!(
+            parsed("'SHA384-AbC='"),
+            SourceExpression::Hash {
+                algorithm: "sha384".to_string(),
+                digest: "AbC=".to_string()
+            }
+        );
+        assert_eq!(
+            parsed("https://*.Example.com:*/static/"),
+            SourceExpression::Host {
+                scheme: Some("https".to_string()),
+                host: "*.example.com".to_string(),
+                port: Some("*".to_string()),
+                path: Some("/static/".to_string())
+            }
+        );
+        assert_eq!(
+            parsed("*"),
+            SourceExpression::Host {
+                scheme: None,
+                host: "*".to_string(),
+                port: None,
+                path: None
+            }
+        );
+        assert_eq!(
+            parsed("'UNSAFE-INLINE'"),
+            SourceExpression::Keyword(Keyword::UnsafeInline)
+        );
+        assert_eq!(
+            parsed("exa mple"),
+            SourceExpression::Other("exa mple".to_string())
+        );
+        for text in &["'nonce-abc123'", "https://*.example.com:*/static/", "data:"] {
+            assert_eq!(parsed(text).to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn unknown_and_duplicate_directives() {
+        let policy = Policy::parse_report_only(
+            "script-src 'none'; Script-Src 'unsafe-eval';; frobnicate-src x; sandbox allow-scripts",
+        );
+        assert!(policy.report_only);
+        assert!(policy
+            .directive(&DirectiveName::ScriptSrc)
+            .unwrap()
+            .has_keyword(Keyword::None));
+        let unknown = policy
+            .directive(&DirectiveName::Unknown("frobnicate-src".to_string()))
+            .unwrap();
+        assert_eq!(unknown.sources.len(), 1);
+        let sandbox = policy.directive(&DirectiveName::Sandbox).unwrap();
+        assert!(sandbox.sources.is_empty());
+        assert_eq!(sandbox.values, vec!["allow-scripts"]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Where in the input a failed block was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorContext {
+    /// Name of the file (or `<stdin>`) being parsed.
+    pub file: String,
+    /// 1-based line number of the `#DebugDoContentSecurityCheck Begin` line,
+    /// or of the failing line if no block was open.
+    pub line: usize,
+    /// The raw (prefix-stripped) block text collected so far.
+    pub block: String,
+}
+
+/// Everything that can go wrong while turning log lines into checks.
+#[derive(Debug)]
+pub enum ParseError {
+    /// A line inside a block carried a cut-off or garbled
+    /// `[Process PID: Thread]: L/CSMLog` prefix.
+    MalformedPrefix {
+        context: ErrorContext,
+        lineno: usize,
+        line: String,
+    },
+    /// The block was not valid YAML for a check.
+    Yaml {
+        context: ErrorContext,
+        error: serde_yaml::Error,
+    },
+    /// The block contained a field we do not know about.
+    UnknownField {
+        context: ErrorContext,
+        field: String,
+    },
+    /// A block was never closed, by a new `Begin` or the end of the input.
+    UnterminatedBlock { context: ErrorContext },
+    /// Reading from the underlying log failed.
+    Io {
+        context: ErrorContext,
+        error: io::Error,
+    },
+    /// A line inside a block was not valid UTF-8.
+    InvalidUtf8 {
+        context: ErrorContext,
+        lineno: usize,
+    },
+}
+
+impl ParseError {
+    pub fn context(&self) -> &ErrorContext {
+        match self {
+            ParseError::MalformedPrefix { context, .. }
+            | ParseError::Yaml { context, .. }
+            | ParseError::UnknownField { context, .. }
+            | ParseError::UnterminatedBlock { context }
+            | ParseError::Io { context, .. }
+            | ParseError::InvalidUtf8 { context, .. } => context,
+        }
+    }
+
+    /// Short, stable name of the error kind, for summaries.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::MalformedPrefix { .. } => "malformed CSMLog prefix",
+            ParseError::Yaml { .. } => "invalid YAML",
+            ParseError::UnknownField { .. } => "unknown field",
+            ParseError::UnterminatedBlock { .. } => "unterminated block",
+            ParseError::Io { .. } => "I/O error",
+            ParseError::InvalidUtf8 { .. } => "invalid UTF-8",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let context = self.context();
+        write!(f, "{}:{}: ", context.file, context.line)?;
+        match self {
+            ParseError::MalformedPrefix { lineno, line, .. } => {
+                write!(f, "malformed CSMLog prefix on line {}: {}", lineno, line)
+            }
+            ParseError::Yaml { error, .. } => write!(f, "invalid YAML: {}", error),
+            ParseError::UnknownField { field, .. } => write!(f, "unknown field {}", field),
+            ParseError::UnterminatedBlock { .. } => write!(f, "unterminated block"),
+            ParseError::Io { error, .. } => write!(f, "I/O error: {}", error),
+            ParseError::InvalidUtf8 { lineno, .. } => {
+                write!(f, "invalid UTF-8 on line {}", lineno)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Yaml { error, .. } => Some(error),
+            ParseError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
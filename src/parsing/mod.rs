@@ -2,33 +2,61 @@
 //use strum;
 //use strum_macros;
 
-pub(crate) mod checktypes;
-pub(crate) mod policytypes;
+pub mod checktypes;
+pub mod csp;
+pub mod error;
+pub mod origin;
+pub mod policytypes;
 pub mod principal;
-mod tests;
+pub mod redirects;
+pub mod securityflags;
+pub mod stream;
+pub(crate) mod tests;
 
-use crate::parsing::checktypes::{CheckLine, ContentSecurityCheck, WrappedCheck};
+use crate::parsing::checktypes::{
+    nest_redirect_chain, CheckLine, ContentSecurityCheck, WrappedCheck,
+};
+pub use crate::parsing::error::ParseError;
 use crate::parsing::policytypes::nsContentPolicyType;
-use log::{error, info, warn};
+pub use crate::parsing::stream::CheckStream;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use log::error;
 
-use regex::Regex;
-use std::io::BufRead;
 use std::str::FromStr;
 
 pub fn parse_contentpolicytype(typestr: &str) -> &'static str {
-    let parsed = nsContentPolicyType::from_str(typestr);
-    if let Ok(cpt) = parsed {
+    let parsed = nsContentPolicyType::parse(typestr);
+    if let Some(cpt) = parsed {
         <&'static str>::from(cpt)
     } else {
         "TYPE_UNKNOWN"
     }
 }
 
+/// Parses a MOZ_LOG timestamp like `2021-04-13 10:00:00.123456` (always
+/// UTC, the ` UTC` suffix is optional) or an RFC 3339 date.
+pub fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(text) {
+        return Some(t.with_timezone(&Utc));
+    }
+    let text = text.trim_end_matches(" UTC");
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .map(|t| DateTime::from_naive_utc_and_offset(t, Utc))
+}
+
 pub fn parsed_content_security_check(
     process_type: ProcessType,
     block: Vec<String>,
 ) -> Result<ContentSecurityCheck, serde_yaml::Error> {
-    let le_block = block.join("\n");
+    let le_block = nest_redirect_chain(&block);
     let deserialized: WrappedCheck = serde_yaml::from_str::<WrappedCheck>(&le_block)?;
     let as_lines: Vec<CheckLine> = deserialized.doContentSecurityCheck;
     let mut check = ContentSecurityCheck::from(as_lines);
@@ -43,73 +71,51 @@ pub enum ProcessType {
     Unknown,
 }
 
+/// MOZ_LOG levels, ordered from least to most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LogLevel {
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Verbose,
+}
+
+impl FromStr for LogLevel {
+    type Err = ();
+
+    /// Parses the single letter MOZ_LOG puts in front of the module name.
+    fn from_str(letter: &str) -> Result<Self, Self::Err> {
+        match letter {
+            "E" => Ok(LogLevel::Error),
+            "W" => Ok(LogLevel::Warning),
+            "I" => Ok(LogLevel::Info),
+            "D" => Ok(LogLevel::Debug),
+            "V" => Ok(LogLevel::Verbose),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Parses a whole log into memory. Blocks that fail to parse are logged and
+/// skipped; use `CheckStream` directly to see those errors or to avoid
+/// holding every check at once.
 pub fn parse_log(
     reader: std::boxed::Box<dyn std::io::BufRead>,
     //    mut outfile: std::boxed::Box<dyn std::io::Write>,
 ) -> std::io::Result<Vec<ContentSecurityCheck>> {
-    const BEGIN_BLOCK: &str = "#DebugDoContentSecurityCheck Begin";
-    const END_BLOCK: &str = "#DebugDoContentSecurityCheck End";
-    let lines = reader.lines().map(|l| l.unwrap());
     let mut blocks: Vec<ContentSecurityCheck> = vec![];
-    let mut current_block: Vec<String> = Vec::with_capacity(30);
-    let mut within_block = false;
-    let mut linecnt = 0;
-    let is_csmlog_line =
-        Regex::new(r"\[(Parent|Child) \d+: Main Thread]: (V|D)/CSMLog (.*)").unwrap();
-    let mut process_type = ProcessType::Unknown;
-    for line in lines {
-        // TODO investigate if we can use == instead of contains(). should be cheaper.
-        if line == BEGIN_BLOCK {
-            linecnt += 1;
-            within_block = true;
-            continue;
-        } else if line == END_BLOCK {
-            within_block = false;
-            if let Ok(parsed_block) =
-                parsed_content_security_check(process_type, current_block.clone())
-            {
-                blocks.push(parsed_block);
-            } else {
-                error!(
-                    "We had to skip a block, because it was not parsable:\n--\n{}\n--",
-                    current_block.join("\n")
-                );
-            }
-            current_block.clear();
-            // emit formerly collected block
-        }
-        if within_block {
-            // append to current block
-            let captures = is_csmlog_line.captures(&line);
-            // 0 = all, 1 = parent/child, 2 = after CSMLog
-            if let Some(caps) = captures {
-                //let caps = captures.unwrap();
-                let process_type_str = caps.get(1).unwrap().as_str();
-                process_type = match process_type_str {
-                    "Child" => ProcessType::Child,
-                    "Parent" => ProcessType::Parent,
-                    _ => {
-                        warn!(
-                            "Noticed unknown string for process type: {}",
-                            process_type_str
-                        );
-                        ProcessType::Unknown
-                    }
-                };
-                let logged_line = caps.get(3).unwrap().as_str();
-                current_block.push(String::from(logged_line));
-            } else {
-                // We are ignoring csmlog lines that aren't part of a security check.
-                // can turn this into an info!() logging call, eventually.
-                warn!("skipping line that isn't a valid csmlog line: {}", line);
-            }
+    for result in CheckStream::new(reader) {
+        match result {
+            Ok(check) => blocks.push(check),
+            Err(ParseError::Io { error, .. }) => return Err(error),
+            Err(e) => error!(
+                "We had to skip a block, because it was not parsable: {}\n--\n{}\n--",
+                e,
+                e.context().block
+            ),
         }
     }
-    info!(
-        "Finished parsing {} lines and received {} blocks",
-        linecnt,
-        blocks.len()
-    );
     Ok(blocks)
 }
 
@@ -117,9 +123,7 @@ pub fn parse_log(
 // FIXME add tests for all parsing cases
 
 // TODO:
-// add code & tests to identify & scan security flags (for now, should just take them as literal strings!)
 // add code & tests for a checkblock, with an enum like Principal to get blocks
-// -- this checkblock should also contain the pid & thread info
 // add code & tests to identify a checkblock in the first place
 
 #[cfg(test)]
@@ -143,9 +147,67 @@ mod tests_parse_contentpolicytype {
 
     #[test]
     fn policy_type_as_number_str() {
-        assert_eq!(parse_contentpolicytype("11"), "TYPE_UNKNOWN");
+        assert_eq!(parse_contentpolicytype("11"), "TYPE_XMLHTTPREQUEST");
+        assert_eq!(parse_contentpolicytype("9"), "TYPE_UNKNOWN");
+    }
+
+    #[test]
+    fn generated_from_idl() {
+        use crate::parsing::policytypes::nsContentPolicyType;
+        assert_eq!(
+            parse_contentpolicytype("TYPE_WEB_TRANSPORT"),
+            "TYPE_WEB_TRANSPORT"
+        );
+        assert_eq!(nsContentPolicyType::TYPE_PING as u32, 10);
+        assert_eq!(nsContentPolicyType::TYPE_UA_FONT as u32, 55);
     }
 }
+#[cfg(test)]
+mod tests_parse_timestamp {
+    use crate::parsing::parse_timestamp;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn moz_log_timestamp() {
+        assert_eq!(
+            parse_timestamp("2021-04-13 10:00:00.123456 UTC"),
+            Some(
+                Utc.with_ymd_and_hms(2021, 4, 13, 10, 0, 0).unwrap()
+                    + chrono::Duration::microseconds(123456)
+            )
+        );
+    }
+
+    #[test]
+    fn without_fraction_or_zone() {
+        assert_eq!(
+            parse_timestamp("2021-04-13 10:00:00"),
+            Utc.with_ymd_and_hms(2021, 4, 13, 10, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn rfc3339_with_offset() {
+        assert_eq!(
+            parse_timestamp("2021-04-13T12:00:00+02:00"),
+            Utc.with_ymd_and_hms(2021, 4, 13, 10, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn date_only() {
+        assert_eq!(
+            parse_timestamp("2021-04-13"),
+            Utc.with_ymd_and_hms(2021, 4, 13, 0, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn garbage() {
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+}
+
 #[cfg(test)]
 mod tests_parse_lines_into_content_security_check_block {
     use crate::parsing::checktypes::{CheckLine, WrappedCheck};
@@ -187,14 +249,48 @@ mod tests_parse_lines_into_content_security_check_block {
             block.push(b.to_owned());
         }
         let p = ProcessType::Unknown;
-        let check = parsed_content_security_check(p, block);
-        assert!(check.is_ok());
+        let check = parsed_content_security_check(p, block).unwrap();
+        let chain = check.redirect_chain().unwrap();
+        assert_eq!(chain.len(), 1);
+        assert_eq!(
+            chain[0].principal.to_string(),
+            "https://www.raspberrypi.org/?wordfence_syncAttackData=14.395"
+        );
+    }
+    #[test]
+    fn test_parse_content_security_check_w_several_redirects() {
+        // As Gecko logs it: the entries line up with the `redirectChain` key.
+        let block: Vec<String> = "doContentSecurityCheck:
+  - channelURI: https://example.com/c
+  - redirectChain:
+    -: http://example.com/a
+    -: https://example.com/b
+  - internalContentPolicyType: TYPE_INTERNAL_SCRIPT
+  - CSP:"
+            .split('\n')
+            .map(String::from)
+            .collect();
+        let check = parsed_content_security_check(ProcessType::Unknown, block).unwrap();
+        let chain: Vec<String> = check
+            .redirect_chain()
+            .unwrap()
+            .iter()
+            .map(|hop| hop.principal.to_string())
+            .collect();
+        assert_eq!(chain, vec!["http://example.com/a", "https://example.com/b"]);
+        assert_eq!(
+            check.internal_content_policy_type().name(),
+            "TYPE_INTERNAL_SCRIPT"
+        );
     }
 }
 
 #[cfg(test)]
 mod tests_parse_log {
-    use crate::parsing::parse_log;
+    use crate::parsing::policytypes::{nsContentPolicyType, ContentPolicyType};
+    use crate::parsing::{
+        parse_log, parse_timestamp, CheckStream, LogLevel, ParseError, ProcessType,
+    };
     use std::io::BufReader;
     #[test]
     fn parse_file_incomplete_block() {
@@ -207,4 +303,190 @@ mod tests_parse_log {
             println!("{:?}", c);
         }*/
     }
+
+    #[test]
+    fn stream_file_incomplete_block() {
+        let f = "src/parsing/tests/block-and-incomplete.txt";
+        let h = std::fs::File::open(f).unwrap();
+        let mut stream = CheckStream::with_source(f, BufReader::new(h));
+        let first = stream.next().unwrap().unwrap();
+        assert_eq!(first.process_type, ProcessType::Parent);
+        assert_eq!(first.pid, Some(17722));
+        assert_eq!(first.thread.as_deref(), Some("Main Thread"));
+        assert_eq!(first.log_level, Some(LogLevel::Verbose));
+        assert!(stream.next().unwrap().is_ok());
+        match stream.next().unwrap() {
+            Err(ParseError::MalformedPrefix {
+                context,
+                lineno,
+                line,
+            }) => {
+                assert_eq!(context.file, f);
+                assert_eq!(context.line, 46);
+                assert!(context.block.starts_with("doContentSecurityCheck:"));
+                assert_eq!(lineno, 49);
+                assert_eq!(line, "[Par");
+            }
+            other => panic!("expected a malformed prefix, got {:?}", other),
+        }
+        assert!(stream.next().is_none());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn stream_interleaved_processes() {
+        let f = "src/parsing/tests/interleaved.txt";
+        let h = std::fs::File::open(f).unwrap();
+        let checks: Vec<_> = CheckStream::new(BufReader::new(h))
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(checks.len(), 2);
+
+        let child = &checks[0];
+        assert_eq!(child.process_type, ProcessType::Child);
+        assert_eq!(child.pid, Some(17801));
+        assert_eq!(child.channel_uri, "https://www.raspberrypi.org/");
+        assert_eq!(child.http_method, Some("GET".to_string()));
+        assert_eq!(child.security_flags.bits(), 8 | 1024);
+        assert_eq!(child.redirect_chain.as_ref().map(|r| r.len()), Some(1));
+
+        let parent = &checks[1];
+        assert_eq!(parent.process_type, ProcessType::Parent);
+        assert_eq!(parent.pid, Some(17722));
+        assert_eq!(parent.http_method, Some("POST".to_string()));
+        assert_eq!(parent.security_flags.bits(), 8 | 32);
+        assert_eq!(parent.redirect_chain, None);
+    }
+
+    #[test]
+    fn stream_interleaved_with_stderr() {
+        // Firefox writes its own messages to the same stderr, right between
+        // the lines of a block.
+        let f = "src/parsing/tests/interleaved.txt";
+        let text = std::fs::read_to_string(f).unwrap();
+        let mut lines: Vec<&str> = text.lines().collect();
+        lines.insert(
+            12,
+            "JavaScript error: resource://gre/modules/Foo.jsm, line 1: TypeError: bar is undefined",
+        );
+        lines.insert(20, "console.warn: \"something\"");
+        let log = lines.join("\n");
+        let with_stderr: Vec<_> = CheckStream::new(log.as_bytes())
+            .map(|r| r.unwrap())
+            .collect();
+        let without: Vec<_> = CheckStream::new(text.as_bytes())
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(with_stderr.len(), 2);
+        assert_eq!(with_stderr, without);
+    }
+
+    fn first_error(log: &[u8]) -> ParseError {
+        CheckStream::new(log)
+            .find_map(|r| r.err())
+            .expect("log should not parse")
+    }
+
+    #[test]
+    fn stream_unterminated_block() {
+        let log = b"#DebugDoContentSecurityCheck Begin\n\
+            [Child 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            #DebugDoContentSecurityCheck Begin\n";
+        match first_error(log) {
+            ParseError::UnterminatedBlock { context } => assert_eq!(context.line, 1),
+            other => panic!("expected an unterminated block, got {:?}", other),
+        }
+        let mut stream = CheckStream::new(&log[..]);
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn stream_worker_thread() {
+        let log = b"#DebugDoContentSecurityCheck Begin\n\
+            [Child 4242: DOM Worker]: D/CSMLog doContentSecurityCheck:\n\
+            [Child 4242: DOM Worker]: D/CSMLog   - channelURI: https://example.com/sw.js\n\
+            [Child 4242: DOM Worker]: D/CSMLog\n\
+            #DebugDoContentSecurityCheck End\n";
+        let check = CheckStream::new(&log[..]).next().unwrap().unwrap();
+        assert_eq!(check.process_type, ProcessType::Child);
+        assert_eq!(check.pid, Some(4242));
+        assert_eq!(check.thread.as_deref(), Some("DOM Worker"));
+        assert_eq!(check.log_level, Some(LogLevel::Debug));
+        assert_eq!(check.channel_uri, "https://example.com/sw.js");
+    }
+
+    #[test]
+    fn stream_timestamps() {
+        let log = b"2021-04-13 10:00:00.000001 UTC - [Parent 1: Main Thread]: D/CSMLog \n\
+            #DebugDoContentSecurityCheck Begin\n\
+            2021-04-13 10:00:00.000002 UTC - [Parent 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            2021-04-13 10:00:00.000003 UTC - [Parent 1: Main Thread]: V/CSMLog   - channelURI: https://example.com/\n\
+            2021-04-13 10:00:00.000004 UTC - [Parent 1: Main Thread]: D/CSMLog \n\
+            #DebugDoContentSecurityCheck End\n";
+        let check = CheckStream::new(&log[..]).next().unwrap().unwrap();
+        assert_eq!(check.channel_uri, "https://example.com/");
+        assert_eq!(
+            check.timestamp,
+            parse_timestamp("2021-04-13 10:00:00.000002")
+        );
+    }
+
+    #[test]
+    fn stream_unknown_field() {
+        let log = b"#DebugDoContentSecurityCheck Begin\n\
+            [Child 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            [Child 1: Main Thread]: V/CSMLog   - channelURI: https://example.com/\n\
+            [Child 1: Main Thread]: V/CSMLog   - frobnicate: true\n\
+            #DebugDoContentSecurityCheck End\n";
+        match first_error(log) {
+            ParseError::UnknownField { field, .. } => assert_eq!(field, "frobnicate"),
+            other => panic!("expected an unknown field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stream_keeps_unknown_policy_type() {
+        let log = b"#DebugDoContentSecurityCheck Begin\n\
+            [Child 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            [Child 1: Main Thread]: V/CSMLog   - channelURI: https://example.com/\n\
+            [Child 1: Main Thread]: V/CSMLog   - internalContentPolicyType: TYPE_FROM_THE_FUTURE\n\
+            [Child 1: Main Thread]: V/CSMLog   - externalContentPolicyType: TYPE_OTHER\n\
+            #DebugDoContentSecurityCheck End\n";
+        let checks: Vec<_> = CheckStream::new(&log[..]).collect();
+        let check = checks[0].as_ref().unwrap();
+        assert_eq!(
+            check.internal_content_policy_type(),
+            &ContentPolicyType::Unknown("TYPE_FROM_THE_FUTURE".to_string())
+        );
+        assert_eq!(
+            *check.external_content_policy_type(),
+            nsContentPolicyType::TYPE_OTHER
+        );
+    }
+
+    #[test]
+    fn stream_invalid_yaml() {
+        let log = b"#DebugDoContentSecurityCheck Begin\n\
+            [Child 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            [Child 1: Main Thread]: V/CSMLog   - channelURI: [\n\
+            #DebugDoContentSecurityCheck End\n";
+        assert!(matches!(first_error(log), ParseError::Yaml { .. }));
+    }
+
+    #[test]
+    fn stream_invalid_utf8() {
+        let log = b"#DebugDoContentSecurityCheck Begin\n\
+            [Child 1: Main Thread]: V/CSMLog doContentSecurityCheck:\n\
+            [Child 1: Main Thread]: V/CSMLog   - channelURI: https://\xff/\n\
+            #DebugDoContentSecurityCheck End\n";
+        match first_error(log) {
+            ParseError::InvalidUtf8 { lineno, context } => {
+                assert_eq!(lineno, 3);
+                assert_eq!(context.line, 1);
+            }
+            other => panic!("expected invalid UTF-8, got {:?}", other),
+        }
+    }
 }
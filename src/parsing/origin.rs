@@ -0,0 +1,149 @@
+//! Origins and sites of URLs the way Gecko computes them for principals.
+
+use publicsuffix::{List, Psl};
+use std::sync::OnceLock;
+use url::{Host, Origin, Url};
+
+/// Vendored copy of https://publicsuffix.org/list/public_suffix_list.dat.
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../../data/public_suffix_list.dat");
+
+fn public_suffix_list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("vendored public suffix list should parse")
+    })
+}
+
+/// The origin of `url`. Unlike `Url::origin`, this looks through `blob:` and
+/// `view-source:` to the inner URL and treats `moz-extension:` as a tuple
+/// origin. Like Gecko, `about:` URLs other than `about:blank` and
+/// `about:srcdoc` have the URL without query and fragment as their origin,
+/// e.g. `about:preferences`; it is kept as a tuple with the page name as the
+/// host, see `serialize_origin`. `data:` URLs get a fresh opaque origin.
+pub fn origin_of(url: &Url) -> Origin {
+    match url.scheme() {
+        "about" => match url.path() {
+            "blank" | "srcdoc" | "" => Origin::new_opaque(),
+            page => Origin::Tuple(
+                url.scheme().to_string(),
+                Host::Domain(page.to_ascii_lowercase()),
+                0,
+            ),
+        },
+        "blob" | "view-source" => match Url::parse(url.path()) {
+            Ok(inner) => origin_of(&inner),
+            Err(_) => Origin::new_opaque(),
+        },
+        "moz-extension" => match url.host() {
+            Some(host) => Origin::Tuple(
+                url.scheme().to_string(),
+                host.to_owned(),
+                url.port().unwrap_or(0),
+            ),
+            None => Origin::new_opaque(),
+        },
+        _ => url.origin(),
+    }
+}
+
+/// Like `Origin::ascii_serialization`, but without the port for origins
+/// of schemes that have none, like `moz-extension:`, and `about:` origins
+/// as Gecko writes them.
+pub fn serialize_origin(origin: &Origin) -> String {
+    match origin {
+        Origin::Tuple(scheme, host, 0) if scheme == "about" => format!("about:{}", host),
+        Origin::Tuple(scheme, host, 0) => format!("{}://{}", scheme, host),
+        _ => origin.ascii_serialization(),
+    }
+}
+
+/// The site of `url`: its origin's scheme plus the registrable domain
+/// (eTLD+1) of its host, e.g. `https://example.co.uk` for
+/// `https://www.example.co.uk:8443/`. Hosts without a registrable domain
+/// (IP addresses, `localhost`, extension UUIDs) are their own site, as are
+/// `about:` pages. Opaque origins have no site.
+pub fn site_of(url: &Url) -> Option<String> {
+    match origin_of(url) {
+        Origin::Opaque(_) => None,
+        origin @ Origin::Tuple(..) if url.scheme() == "about" => Some(serialize_origin(&origin)),
+        Origin::Tuple(scheme, Host::Domain(domain), _) => {
+            let site = public_suffix_list()
+                .domain(domain.as_bytes())
+                .and_then(|d| std::str::from_utf8(d.as_bytes()).ok().map(String::from))
+                .unwrap_or(domain);
+            Some(format!("{}://{}", scheme, site))
+        }
+        Origin::Tuple(scheme, host, _) => Some(format!("{}://{}", scheme, host)),
+    }
+}
+
+#[cfg(test)]
+mod tests_origin {
+    use super::{origin_of, serialize_origin, site_of};
+    use url::Url;
+
+    fn site(text: &str) -> Option<String> {
+        site_of(&Url::parse(text).unwrap())
+    }
+
+    fn origin(text: &str) -> String {
+        serialize_origin(&origin_of(&Url::parse(text).unwrap()))
+    }
+
+    #[test]
+    fn special_schemes() {
+        assert_eq!(
+            origin("https://example.com:8443/a"),
+            "https://example.com:8443"
+        );
+        assert_eq!(
+            origin("blob:https://example.com/2d9f-4a3b"),
+            "https://example.com"
+        );
+        assert_eq!(
+            origin("view-source:https://example.com/a"),
+            "https://example.com"
+        );
+        assert_eq!(
+            origin("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/bg.js"),
+            "moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee"
+        );
+        assert_eq!(origin("about:blank"), "null");
+        assert_eq!(origin("about:srcdoc"), "null");
+        assert_eq!(origin("about:config"), "about:config");
+        assert_eq!(
+            origin("about:preferences?entrypoint=menu#privacy"),
+            "about:preferences"
+        );
+        assert_eq!(origin("data:text/html,hi"), "null");
+    }
+
+    #[test]
+    fn sites() {
+        assert_eq!(
+            site("https://www.example.co.uk:8443/"),
+            Some("https://example.co.uk".to_string())
+        );
+        // github.io is in the private section of the list.
+        assert_eq!(
+            site("https://mozilla.github.io/"),
+            Some("https://mozilla.github.io".to_string())
+        );
+        assert_eq!(
+            site("http://127.0.0.1:8000/"),
+            Some("http://127.0.0.1".to_string())
+        );
+        assert_eq!(
+            site("http://localhost/"),
+            Some("http://localhost".to_string())
+        );
+        assert_eq!(site("data:text/html,hi"), None);
+        assert_eq!(
+            site("about:preferences#home"),
+            Some("about:preferences".to_string())
+        );
+        assert_eq!(site("about:blank"), None);
+    }
+}
@@ -1,415 +1,241 @@
-#![rustfmt::skip]
 #![allow(clippy::upper_case_acronyms)]
-/**
-Generated though:
-1) Verbatim copy of the `cenum nsContentPolicyType ..` block from
-  https://searchfox.org/mozilla-central/source/dom/base/nsIContentPolicy.idl
-2) but rustified through "pub enum", the allow-non-camel-case annotation
-3)  and the derive() block
- */
-
-/// FIXME: Generate this with a build-time script
-
-use strum_macros::{EnumString, IntoStaticStr};
-  /**
-   * The type of nsIContentPolicy::TYPE_*
-   */
-  #[allow(non_camel_case_types)]
-  #[derive(Debug, PartialEq, EnumString, IntoStaticStr, Serialize, Deserialize)]
-  pub enum nsContentPolicyType {
-    /**
-     * Indicates a unset or bogus policy type.
-     */
-    TYPE_INVALID = 0,
-
-    /**
-     * Gecko/Firefox developers: Avoid using TYPE_OTHER. Especially for
-     * requests that are coming from webpages. Or requests in general which
-     * you expect that security checks will be done on.
-     * Always use a more specific type if one is available. And do not hesitate
-     * to add more types as appropriate.
-     * But if you are fairly sure that no one would care about your more specific
-     * type, then it's ok to use TYPE_OTHER.
-     *
-     * Extension developers: Whenever it is reasonable, use one of the existing
-     * content types. If none of the existing content types are right for
-     * something you are doing, file a bug in the Core/DOM component that
-     * includes a patch that adds your new content type to the end of the list of
-     * TYPE_* constants here. But, don't start using your new content type until
-     * your patch has been accepted, because it will be uncertain what exact
-     * value and name your new content type will have; in that interim period,
-     * use TYPE_OTHER. In your patch, document your new content type in the style
-     * of the existing ones. In the bug you file, provide a more detailed
-     * description of the new type of content you want Gecko to support, so that
-     * the existing implementations of nsIContentPolicy can be properly modified
-     * to deal with that new type of content.
-     *
-     * Implementations of nsIContentPolicy should treat this the same way they
-     * treat unknown types, because existing users of TYPE_OTHER may be converted
-     * to use new content types.
-     *
-     * Note that the TYPE_INTERNAL_* constants are never passed to content
-     * policy implementations.  They are mapped to other TYPE_* constants, and
-     * are only intended for internal usage inside Gecko.
-     */
-    TYPE_OTHER = 1,
-
-    /**
-     * Indicates an executable script (such as JavaScript).
-     */
-    TYPE_SCRIPT = 2,
-
-    /**
-     * Indicates an image (e.g., IMG elements).
-     */
-    TYPE_IMAGE = 3,
-
-    /**
-     * Indicates a stylesheet (e.g., STYLE elements).
-     */
-    TYPE_STYLESHEET = 4,
-
-    /**
-     * Indicates a generic object (plugin-handled content typically falls under
-     * this category).
-     */
-    TYPE_OBJECT = 5,
-
-    /**
-     * Indicates a document at the top-level (i.e., in a browser).
-     */
-    TYPE_DOCUMENT = 6,
-
-    /**
-     * Indicates a document contained within another document (e.g., IFRAMEs,
-     * FRAMES, and OBJECTs).
-     */
-    TYPE_SUBDOCUMENT = 7,
-
-    /*
-     * XXX: nsContentPolicyType = 8 used to inicate a timed refresh request.
-     */
-
-    /*
-     * XXX: nsContentPolicyType = 9 used to inicate an XBL binding request.
-     */
-
-    /**
-     * Indicates a ping triggered by a click on <A PING="..."> element.
-     */
-    TYPE_PING = 10,
-
-    /**
-     * Indicates an XMLHttpRequest. Also used for document.load and for EventSource.
-     */
-    TYPE_XMLHTTPREQUEST = 11,
-
-    /**
-     * Indicates a request by a plugin.
-     */
-    TYPE_OBJECT_SUBREQUEST = 12,
-
-    /**
-     * Indicates a DTD loaded by an XML document.
-     */
-    TYPE_DTD = 13,
-
-    /**
-     * Indicates a font loaded via @font-face rule.
-     */
-    TYPE_FONT = 14,
-
-    /**
-     * Indicates a video or audio load.
-     */
-    TYPE_MEDIA = 15,
-
-    /**
-     * Indicates a WebSocket load.
-     */
-    TYPE_WEBSOCKET = 16,
-
-    /**
-     * Indicates a Content Security Policy report.
-     */
-    TYPE_CSP_REPORT = 17,
-
-    /**
-     * Indicates a style sheet transformation.
-     */
-    TYPE_XSLT = 18,
-
-    /**
-     * Indicates a beacon post.
-     */
-    TYPE_BEACON = 19,
-
-    /**
-     * Indicates a load initiated by the fetch() function from the Fetch
-     * specification.
-     */
-    TYPE_FETCH = 20,
-
-    /**
-     * Indicates a <img srcset> or <picture> request.
-     */
-    TYPE_IMAGESET = 21,
-
-    /**
-     * Indicates a web manifest.
-     */
-    TYPE_WEB_MANIFEST = 22,
-
-    /**
-     * Indicates an internal constant for scripts loaded through script
-     * elements.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_SCRIPT = 23,
-
-    /**
-     * Indicates an internal constant for scripts loaded through a dedicated
-     * worker.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_WORKER = 24,
-
-    /**
-     * Indicates an internal constant for scripts loaded through a shared
-     * worker.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_SHARED_WORKER = 25,
-
-    /**
-     * Indicates an internal constant for content loaded from embed elements.
-     *
-     * This will be mapped to TYPE_OBJECT.
-     */
-    TYPE_INTERNAL_EMBED = 26,
-
-    /**
-     * Indicates an internal constant for content loaded from object elements.
-     *
-     * This will be mapped to TYPE_OBJECT.
-     */
-    TYPE_INTERNAL_OBJECT = 27,
-
-    /**
-     * Indicates an internal constant for content loaded from frame elements.
-     *
-     * This will be mapped to TYPE_SUBDOCUMENT.
-     */
-    TYPE_INTERNAL_FRAME = 28,
-
-    /**
-     * Indicates an internal constant for content loaded from iframe elements.
-     *
-     * This will be mapped to TYPE_SUBDOCUMENT.
-     */
-    TYPE_INTERNAL_IFRAME = 29,
-
-    /**
-     * Indicates an internal constant for content loaded from audio elements.
-     *
-     * This will be mapped to TYPE_MEDIA.
-     */
-    TYPE_INTERNAL_AUDIO = 30,
-
-    /**
-     * Indicates an internal constant for content loaded from video elements.
-     *
-     * This will be mapped to TYPE_MEDIA.
-     */
-    TYPE_INTERNAL_VIDEO = 31,
-
-    /**
-     * Indicates an internal constant for content loaded from track elements.
-     *
-     * This will be mapped to TYPE_MEDIA.
-     */
-    TYPE_INTERNAL_TRACK = 32,
-
-    /**
-     * Indicates an internal constant for an XMLHttpRequest.
-     *
-     * This will be mapped to TYPE_XMLHTTPREQUEST.
-     */
-    TYPE_INTERNAL_XMLHTTPREQUEST = 33,
-
-    /**
-     * Indicates an internal constant for EventSource.
-     *
-     * This will be mapped to TYPE_XMLHTTPREQUEST.
-     */
-    TYPE_INTERNAL_EVENTSOURCE = 34,
-
-    /**
-     * Indicates an internal constant for scripts loaded through a service
-     * worker.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_SERVICE_WORKER = 35,
-
-    /**
-     * Indicates an internal constant for *preloaded* scripts
-     * loaded through script elements.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_SCRIPT_PRELOAD = 36,
-
-    /**
-     * Indicates an internal constant for normal images.
-     *
-     * This will be mapped to TYPE_IMAGE before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_IMAGE = 37,
-
-    /**
-     * Indicates an internal constant for *preloaded* images.
-     *
-     * This will be mapped to TYPE_IMAGE before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_IMAGE_PRELOAD = 38,
-
-    /**
-     * Indicates an internal constant for normal stylesheets.
-     *
-     * This will be mapped to TYPE_STYLESHEET before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_STYLESHEET = 39,
-
-    /**
-     * Indicates an internal constant for *preloaded* stylesheets.
-     *
-     * This will be mapped to TYPE_STYLESHEET before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_STYLESHEET_PRELOAD = 40,
-
-    /**
-     * Indicates an internal constant for favicon.
-     *
-     * This will be mapped to TYPE_IMAGE before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_IMAGE_FAVICON = 41,
-
-    /**
-     * Indicates an importScripts() inside a worker script.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_WORKER_IMPORT_SCRIPTS = 42,
-
-    /**
-     * Indicates an save-as link download from the front-end code.
-     */
-    TYPE_SAVEAS_DOWNLOAD = 43,
-
-    /**
-     * Indicates a speculative connection.
-     */
-    TYPE_SPECULATIVE = 44,
-
-    /**
-     * Indicates an internal constant for ES6 module scripts
-     * loaded through script elements or an import statement.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_MODULE = 45,
-
-    /**
-     * Indicates an internal constant for *preloaded* ES6 module scripts
-     * loaded through script elements or an import statement.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_MODULE_PRELOAD = 46,
-
-    /**
-     * Indicates a DTD loaded by an XML document the URI of which could
-     * not be mapped to a known local DTD.
-     */
-    TYPE_INTERNAL_DTD = 47,
-
-    /**
-     * Indicates a TYPE_INTERNAL_DTD which will not be blocked no matter
-     * what principal is being loaded from.
-     */
-    TYPE_INTERNAL_FORCE_ALLOWED_DTD = 48,
-
-    /**
-     * Indicates an internal constant for scripts loaded through an
-     * audioWorklet.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_AUDIOWORKLET = 49,
-
-    /**
-     * Indicates an internal constant for scripts loaded through an
-     * paintWorklet.
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-     */
-    TYPE_INTERNAL_PAINTWORKLET = 50,
-
-    /**
-     * Same as TYPE_FONT but indicates this is a <link rel=preload as=font>
-     * preload initiated load.
-     */
-    TYPE_INTERNAL_FONT_PRELOAD = 51,
-
-    /**
-     * Indicates the load of a (Firefox-internal) script through ChromeUtils
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-    */
-    TYPE_INTERNAL_CHROMEUTILS_COMPILED_SCRIPT = 52,
-
-    /**
-     * Indicates the load of a script through FrameMessageManager
-     *
-     * This will be mapped to TYPE_SCRIPT before being passed to content policy
-     * implementations.
-    */
-    TYPE_INTERNAL_FRAME_MESSAGEMANAGER_SCRIPT = 53,
-
-    /**
-     * Indicates an internal constant for *preloaded* fetch
-     * loaded through link elements.
-     *
-     * This will be mapped to TYPE_FETCH before being passed
-     * to content policy implementations.
-     */
-    TYPE_INTERNAL_FETCH_PRELOAD = 54,
-
-    /* When adding new content types, please update
-     * NS_CP_ContentTypeName, nsCSPContext, CSP_ContentTypeToDirective,
-     * DoContentSecurityChecks, all nsIContentPolicy implementations, the
-     * static_assert in dom/cache/DBSchema.cpp, ChannelWrapper.webidl,
-     * ChannelWrapper.cpp, PermissionManager.cpp,
-     * IPCMessageUtilsSpecializations.h, and other things that are not
-     * listed here that are related to nsIContentPolicy. */
-  }
+//! `nsContentPolicyType`, generated by `build.rs` from `nsIContentPolicy.idl`
+//! along with its mapping to external types and categories.
+
+use log::warn;
+use serde::de::Deserializer;
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use strum_macros::{EnumString, EnumVariantNames, IntoStaticStr};
+
+include!(concat!(env!("OUT_DIR"), "/policytypes.rs"));
+
+impl fmt::Display for nsContentPolicyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.into())
+    }
+}
+
+/// Coarse grouping of content policy types, for filtering and reports.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    EnumString,
+    IntoStaticStr,
+    EnumVariantNames,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum PolicyCategory {
+    Script,
+    Style,
+    Image,
+    Media,
+    Document,
+    Fetch,
+    Font,
+    Other,
+}
+
+impl fmt::Display for PolicyCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.into())
+    }
+}
+
+impl nsContentPolicyType {
+    /// Parses a type name like `TYPE_SCRIPT` or its numeric value like `2`.
+    pub fn parse(text: &str) -> Option<nsContentPolicyType> {
+        nsContentPolicyType::from_str(text)
+            .ok()
+            .or_else(|| text.parse().ok().and_then(|n: u32| Self::try_from(n).ok()))
+    }
+
+    /// Whether Gecko maps this type to another one before handing it to
+    /// content policies, see `to_external`.
+    pub fn is_internal(self) -> bool {
+        self.to_external() != self
+    }
+
+    /// Top-level documents and frames.
+    pub fn is_document_like(self) -> bool {
+        use nsContentPolicyType::*;
+        matches!(self.to_external(), TYPE_DOCUMENT | TYPE_SUBDOCUMENT)
+    }
+}
+
+/// A logged content policy type. Logs of a Firefox newer than the IDL we
+/// were built from may contain types we do not know about; those are kept
+/// by name instead of failing the whole check.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContentPolicyType {
+    Known(nsContentPolicyType),
+    Unknown(String),
+}
+
+/// Names we already warned about, so a log full of them only warns once each.
+static WARNED_UNKNOWN: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+impl ContentPolicyType {
+    /// Parses a logged type name or number, warning once per name we do
+    /// not know.
+    pub fn from_logged(name: &str) -> Self {
+        match nsContentPolicyType::parse(name) {
+            Some(known) => ContentPolicyType::Known(known),
+            None => {
+                if let Ok(mut warned) = WARNED_UNKNOWN.lock() {
+                    if warned.insert(name.to_string()) {
+                        warn!(
+                            "Unknown content policy type {}, nsContentPolicyType needs to be \
+                             regenerated from a newer nsIContentPolicy.idl",
+                            name
+                        );
+                    }
+                }
+                ContentPolicyType::Unknown(name.to_string())
+            }
+        }
+    }
+
+    pub fn known(&self) -> Option<nsContentPolicyType> {
+        match self {
+            ContentPolicyType::Known(known) => Some(*known),
+            ContentPolicyType::Unknown(_) => None,
+        }
+    }
+
+    /// See `nsContentPolicyType::to_external`. Unknown types stay as they are.
+    pub fn to_external(&self) -> ContentPolicyType {
+        match self {
+            ContentPolicyType::Known(known) => ContentPolicyType::Known(known.to_external()),
+            ContentPolicyType::Unknown(_) => self.clone(),
+        }
+    }
+
+    /// Unknown types are `PolicyCategory::Other`.
+    pub fn category(&self) -> PolicyCategory {
+        self.known().map_or(PolicyCategory::Other, |t| t.category())
+    }
+
+    /// The name as logged, e.g. `TYPE_SCRIPT`.
+    pub fn name(&self) -> &str {
+        match self {
+            ContentPolicyType::Known(known) => known.into(),
+            ContentPolicyType::Unknown(name) => name,
+        }
+    }
+}
+
+impl Default for ContentPolicyType {
+    fn default() -> Self {
+        ContentPolicyType::Known(nsContentPolicyType::TYPE_INVALID)
+    }
+}
+
+impl From<nsContentPolicyType> for ContentPolicyType {
+    fn from(known: nsContentPolicyType) -> Self {
+        ContentPolicyType::Known(known)
+    }
+}
+
+impl PartialEq<nsContentPolicyType> for ContentPolicyType {
+    fn eq(&self, other: &nsContentPolicyType) -> bool {
+        self.known() == Some(*other)
+    }
+}
+
+impl fmt::Display for ContentPolicyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for ContentPolicyType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentPolicyType {
+    fn deserialize<D>(deserializer: D) -> Result<ContentPolicyType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Ok(ContentPolicyType::from_logged(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests_content_policy_type {
+    use super::{nsContentPolicyType, ContentPolicyType};
+
+    #[test]
+    fn known_and_unknown() {
+        assert_eq!(
+            ContentPolicyType::from_logged("TYPE_SCRIPT"),
+            nsContentPolicyType::TYPE_SCRIPT
+        );
+        let unknown = ContentPolicyType::from_logged("TYPE_FROM_THE_FUTURE");
+        assert_eq!(unknown.known(), None);
+        assert_eq!(unknown.to_string(), "TYPE_FROM_THE_FUTURE");
+    }
+
+    #[test]
+    fn numeric_round_trip() {
+        use std::convert::TryFrom;
+        for &t in nsContentPolicyType::ALL {
+            assert_eq!(nsContentPolicyType::try_from(t as u32), Ok(t));
+        }
+        assert_eq!(nsContentPolicyType::try_from(8), Err(8));
+        assert_eq!(
+            ContentPolicyType::from_logged("2"),
+            nsContentPolicyType::TYPE_SCRIPT
+        );
+    }
+
+    #[test]
+    fn predicates_and_categories() {
+        use super::PolicyCategory;
+        use nsContentPolicyType::*;
+        assert!(TYPE_INTERNAL_IMAGE_FAVICON.is_internal());
+        assert!(!TYPE_IMAGE.is_internal());
+        assert!(TYPE_INTERNAL_FONT_PRELOAD.is_preload());
+        assert!(TYPE_INTERNAL_SERVICE_WORKER.is_worker());
+        assert!(!TYPE_INTERNAL_AUDIOWORKLET.is_worker());
+        assert!(TYPE_INTERNAL_IFRAME.is_document_like());
+        assert_eq!(
+            TYPE_INTERNAL_AUDIOWORKLET.category(),
+            PolicyCategory::Script
+        );
+        assert_eq!(TYPE_INTERNAL_EVENTSOURCE.category(), PolicyCategory::Fetch);
+        assert_eq!(TYPE_UA_FONT.category(), PolicyCategory::Font);
+        assert_eq!(
+            ContentPolicyType::from_logged("TYPE_NEWFANGLED").category(),
+            PolicyCategory::Other
+        );
+    }
+
+    #[test]
+    fn internal_to_external() {
+        use nsContentPolicyType::*;
+        assert_eq!(TYPE_INTERNAL_MODULE_PRELOAD.to_external(), TYPE_SCRIPT);
+        assert_eq!(TYPE_INTERNAL_IFRAME.to_external(), TYPE_SUBDOCUMENT);
+        assert_eq!(TYPE_INTERNAL_EVENTSOURCE.to_external(), TYPE_XMLHTTPREQUEST);
+        assert_eq!(TYPE_UA_FONT.to_external(), TYPE_FONT);
+        assert_eq!(TYPE_DOCUMENT.to_external(), TYPE_DOCUMENT);
+    }
+}
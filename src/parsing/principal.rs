@@ -1,17 +1,181 @@
-use serde::de::{Deserialize, Deserializer, Unexpected, Visitor};
+use crate::parsing::origin::{origin_of, site_of};
+use log::warn;
+use serde::de::{Deserialize, Deserializer, Visitor};
 use serde::ser::{Serialize, Serializer};
-use serde_json::Error;
 use std::fmt;
 use std::str::FromStr;
-use url::Url;
+use url::form_urlencoded;
+use url::{Origin, Url};
 
-#[derive(Debug, PartialEq)]
+/// The `^key=value&...` suffix Gecko appends to origins to keep container
+/// tabs, private browsing and first-party/partitioned state apart. Values
+/// equal to their default are not serialized.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct OriginAttributes {
+    pub user_context_id: u32,
+    pub private_browsing_id: u32,
+    pub first_party_domain: String,
+    pub gecko_view_session_context_id: String,
+    pub partition_key: String,
+    /// Attributes we do not know about, kept for `Display`.
+    pub other: Vec<(String, String)>,
+}
+
+impl OriginAttributes {
+    pub fn is_default(&self) -> bool {
+        *self == OriginAttributes::default()
+    }
+
+    pub fn is_private_browsing(&self) -> bool {
+        self.private_browsing_id != 0
+    }
+
+    /// Whether the principal lives in a container tab.
+    pub fn is_container(&self) -> bool {
+        self.user_context_id != 0
+    }
+
+    /// Parses a suffix with or without its leading `^`.
+    pub fn from_suffix(suffix: &str) -> Result<Self, String> {
+        let suffix = suffix.strip_prefix('^').unwrap_or(suffix);
+        let mut attrs = OriginAttributes::default();
+        for pair in suffix.split('&') {
+            if !pair.contains('=') {
+                return Err(format!("expected key=value, got {:?}", pair));
+            }
+        }
+        for (key, value) in form_urlencoded::parse(suffix.as_bytes()) {
+            let number = || {
+                value
+                    .parse()
+                    .map_err(|_| format!("{} is not a number: {:?}", key, value))
+            };
+            match key.as_ref() {
+                "userContextId" => attrs.user_context_id = number()?,
+                "privateBrowsingId" => attrs.private_browsing_id = number()?,
+                "firstPartyDomain" => attrs.first_party_domain = value.into_owned(),
+                "geckoViewUserContextId" | "geckoViewSessionContextId" => {
+                    attrs.gecko_view_session_context_id = value.into_owned()
+                }
+                "partitionKey" => attrs.partition_key = value.into_owned(),
+                _ => attrs.other.push((key.into_owned(), value.into_owned())),
+            }
+        }
+        Ok(attrs)
+    }
+
+    /// The suffix in Gecko's order, including the leading `^`, or an empty
+    /// string for default attributes.
+    pub fn to_suffix(&self) -> String {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        if self.user_context_id != 0 {
+            serializer.append_pair("userContextId", &self.user_context_id.to_string());
+        }
+        if self.private_browsing_id != 0 {
+            serializer.append_pair("privateBrowsingId", &self.private_browsing_id.to_string());
+        }
+        if !self.first_party_domain.is_empty() {
+            serializer.append_pair("firstPartyDomain", &self.first_party_domain);
+        }
+        if !self.gecko_view_session_context_id.is_empty() {
+            serializer.append_pair(
+                "geckoViewSessionContextId",
+                &self.gecko_view_session_context_id,
+            );
+        }
+        if !self.partition_key.is_empty() {
+            serializer.append_pair("partitionKey", &self.partition_key);
+        }
+        for (key, value) in &self.other {
+            serializer.append_pair(key, value);
+        }
+        let pairs = serializer.finish();
+        if pairs.is_empty() {
+            pairs
+        } else {
+            format!("^{}", pairs)
+        }
+    }
+}
+
+/// A principal for a web page or other URL-based content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContentPrincipal {
+    pub url: Url,
+    pub origin_attributes: OriginAttributes,
+}
+
+impl FromStr for ContentPrincipal {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // Like Gecko's `PopulateFromOrigin`, the suffix starts at the last `^`.
+        let (spec, origin_attributes) = match text.rfind('^') {
+            Some(pos) => match OriginAttributes::from_suffix(&text[pos..]) {
+                Ok(attrs) => (&text[..pos], attrs),
+                Err(_) => (text, OriginAttributes::default()),
+            },
+            None => (text, OriginAttributes::default()),
+        };
+        let url = Url::parse(spec).map_err(|e| format!("{}: {:?}", e, spec))?;
+        Ok(ContentPrincipal {
+            url,
+            origin_attributes,
+        })
+    }
+}
+
+impl fmt::Display for ContentPrincipal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.url, self.origin_attributes.to_suffix())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Principal {
-    ContentPrincipal(String),
+    ContentPrincipal(ContentPrincipal),
     ExpandedPrincipal(Vec<Principal>),
     SystemPrincipal,
     NullPrincipal,
     NullPtr,
+    /// A principal string that is not a URL, kept as logged.
+    UnparsedPrincipal(String),
+}
+
+impl Principal {
+    /// The origin of a content principal, see `origin_of`. Other principals
+    /// have none we could compare.
+    pub fn origin(&self) -> Option<Origin> {
+        match self {
+            Principal::ContentPrincipal(p) => Some(origin_of(&p.url)),
+            _ => None,
+        }
+    }
+
+    /// The site (scheme plus eTLD+1) of a content principal, see `site_of`.
+    pub fn site(&self) -> Option<String> {
+        match self {
+            Principal::ContentPrincipal(p) => site_of(&p.url),
+            _ => None,
+        }
+    }
+
+    /// Whether `url` is same-origin with this principal. Opaque origins are
+    /// never same-origin with anything.
+    pub fn is_same_origin(&self, url: &Url) -> bool {
+        match self.origin() {
+            Some(origin @ Origin::Tuple(..)) => origin == origin_of(url),
+            _ => false,
+        }
+    }
+
+    pub fn is_same_site(&self, url: &Url) -> bool {
+        match self.site() {
+            Some(site) => Some(site) == site_of(url),
+            None => false,
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for Principal {
@@ -33,14 +197,12 @@ impl<'de> Deserialize<'de> for Principal {
             where
                 E: serde::de::Error,
             {
-                //Principal::from_str(value)
-                match Principal::from_str(value) {
-                    Ok(p) => Ok(p),
-                    Err(e) => {
-                        println!("err {}", e);
-                        Err(serde::de::Error::custom("not a JSON Principal"))
-                    }
-                }
+                // Rather keep the check with a principal we can not make
+                // sense of than drop it.
+                Ok(Principal::from_str(value).unwrap_or_else(|e| {
+                    warn!("{}", e);
+                    Principal::UnparsedPrincipal(value.to_string())
+                }))
             }
         }
         deserializer.deserialize_any(PrincipalVisitor)
@@ -52,47 +214,144 @@ impl Serialize for Principal {
     where
         S: Serializer,
     {
-        // 3 is the number of fields in the struct.
         serializer.serialize_str(&self.to_string())
     }
 }
 
+/// Why a principal string could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrincipalError {
+    pub input: String,
+    pub reason: String,
+}
+
+impl fmt::Display for PrincipalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "can not parse principal {:?}: {}",
+            self.input, self.reason
+        )
+    }
+}
+
+impl std::error::Error for PrincipalError {}
+
+const EXPANDED_PREFIX: &str = "[Expanded Principal [";
+const EXPANDED_SUFFIX: &str = "]]";
+
+/// Whether `text` starts like one of the principals in an expanded
+/// principal's allowlist: a nested expanded principal, one of the keywords
+/// or a URL scheme.
+fn starts_principal(text: &str) -> bool {
+    if text.starts_with(EXPANDED_PREFIX)
+        || ["SystemPrincipal", "NullPrincipal", "nullptr"]
+            .iter()
+            .any(|keyword| text.starts_with(keyword))
+    {
+        return true;
+    }
+    match text.find(':') {
+        Some(colon) => {
+            let scheme = &text[..colon];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Splits the allowlist of an expanded principal into its principals.
+///
+/// Entries are separated by spaces or `, ` (newer Gecko). A space only
+/// separates if a new principal starts after it, so URLs containing spaces
+/// stay whole. Nested expanded principals are kept as one entry, and a
+/// backslash escapes the next character.
+fn split_allowlist(list: &str) -> Result<Vec<String>, String> {
+    let mut entries = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut rest = list;
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' {
+            let escaped = rest[1..]
+                .chars()
+                .next()
+                .ok_or_else(|| "dangling escape at the end".to_string())?;
+            if depth > 0 {
+                // Nested lists are split again later, keep their escapes.
+                current.push('\\');
+            }
+            current.push(escaped);
+            rest = &rest[1 + escaped.len_utf8()..];
+            continue;
+        }
+        if rest.starts_with(EXPANDED_PREFIX) {
+            depth += 1;
+            current.push_str(EXPANDED_PREFIX);
+            rest = &rest[EXPANDED_PREFIX.len()..];
+            continue;
+        }
+        if depth > 0 && rest.starts_with(EXPANDED_SUFFIX) {
+            depth -= 1;
+            current.push_str(EXPANDED_SUFFIX);
+            rest = &rest[EXPANDED_SUFFIX.len()..];
+            continue;
+        }
+        if depth == 0 && (c == ' ' || c == ',') {
+            let next = rest.trim_start_matches([' ', ',']);
+            if !current.is_empty() && (next.is_empty() || starts_principal(next)) {
+                entries.push(std::mem::take(&mut current));
+                rest = next;
+                continue;
+            }
+        }
+        current.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if depth > 0 {
+        return Err("unbalanced nested expanded principal".to_string());
+    }
+    if !current.is_empty() {
+        entries.push(current);
+    }
+    Ok(entries)
+}
+
 impl FromStr for Principal {
-    type Err = Error;
+    type Err = PrincipalError;
 
+    /// Strings that are neither one of the keywords, an expanded principal
+    /// nor a URL become an `UnparsedPrincipal`. Only malformed expanded
+    /// principals are an error.
     fn from_str(text: &str) -> Result<Self, Self::Err> {
-        // serde::de::Error
+        let error = |reason: String| PrincipalError {
+            input: text.to_string(),
+            reason,
+        };
         match text {
             "SystemPrincipal" => Ok(Principal::SystemPrincipal),
             "NullPrincipal" => Ok(Principal::NullPrincipal),
             "nullptr" => Ok(Principal::NullPtr),
-            prin_str => {
-                // parse URL
-                //if starts with [Expa]
-                if prin_str.starts_with("[Expanded Principal [") && prin_str.ends_with("]]") {
-                    let mut principals: Vec<Principal> = vec![];
-                    let str_len = prin_str.len();
-                    let inner = &prin_str[21..str_len - 2];
-                    for value in inner.split(' ') {
-                        if let Ok(principal) = Principal::from_str(value) {
-                            principals.push(principal);
-                        } else {
-                            return Err(serde::de::Error::invalid_type(
-                                Unexpected::Str("Error parsing inner principal"),
-                                &value,
-                            ));
-                        }
-                    }
-                    Ok(Principal::ExpandedPrincipal(principals))
-                } else if let Ok(url) = Url::parse(prin_str) {
-                    Ok(Principal::ContentPrincipal(url.into_string()))
-                } else {
-                    Err(serde::de::Error::invalid_type(
-                        Unexpected::Str("Error parsing into principal"),
-                        &prin_str,
-                    ))
+            _ if text.starts_with(EXPANDED_PREFIX) => {
+                let list = text[EXPANDED_PREFIX.len()..]
+                    .strip_suffix(EXPANDED_SUFFIX)
+                    .ok_or_else(|| error(format!("missing closing {:?}", EXPANDED_SUFFIX)))?;
+                let mut principals = vec![];
+                for entry in split_allowlist(list).map_err(error)? {
+                    principals.push(
+                        Principal::from_str(&entry)
+                            .map_err(|e| error(format!("in allowlist: {}", e)))?,
+                    );
                 }
+                Ok(Principal::ExpandedPrincipal(principals))
             }
+            _ => Ok(match ContentPrincipal::from_str(text) {
+                Ok(content) => Principal::ContentPrincipal(content),
+                Err(_) => Principal::UnparsedPrincipal(text.to_string()),
+            }),
         }
     }
 }
@@ -104,7 +363,7 @@ impl fmt::Display for Principal {
             Principal::NullPrincipal => "NullPrincipal".into(),
             Principal::NullPtr => "nullptr".into(),
             Principal::ExpandedPrincipal(v) => {
-                let mut t = "[Expanded Principal [".to_string();
+                let mut t = EXPANDED_PREFIX.to_string();
                 let mut i = v.len();
                 for u in v {
                     t.push_str(&(u.to_string()));
@@ -113,10 +372,11 @@ impl fmt::Display for Principal {
                     }
                     i -= 1;
                 }
-                t.push_str("]]");
+                t.push_str(EXPANDED_SUFFIX);
                 t
             }
-            Principal::ContentPrincipal(u) => u.clone(),
+            Principal::ContentPrincipal(u) => u.to_string(),
+            Principal::UnparsedPrincipal(text) => text.clone(),
         };
         write!(f, "{}", s)
     }
@@ -131,7 +391,7 @@ mod tests_principal_from_str {
     fn parse_http_url() {
         assert_eq!(
             Principal::from_str("http://example.com/").unwrap(),
-            Principal::ContentPrincipal("http://example.com/".to_string())
+            Principal::ContentPrincipal("http://example.com/".parse().unwrap())
         );
     }
 
@@ -139,7 +399,7 @@ mod tests_principal_from_str {
     fn parse_about_url() {
         assert_eq!(
             Principal::from_str("about:config").unwrap(),
-            Principal::ContentPrincipal("about:config".to_string())
+            Principal::ContentPrincipal("about:config".parse().unwrap())
         );
     }
 
@@ -161,7 +421,7 @@ mod tests_principal_from_str {
         assert_eq!(
             Principal::from_str("[Expanded Principal [https://example.com/]]").unwrap(),
             Principal::ExpandedPrincipal(vec![Principal::ContentPrincipal(
-                "https://example.com/".to_string()
+                "https://example.com/".parse().unwrap()
             )])
         );
     }
@@ -171,8 +431,8 @@ mod tests_principal_from_str {
         assert_eq!(
             Principal::from_str("[Expanded Principal [moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/ https://example.com/]]").unwrap(),
             Principal::ExpandedPrincipal(vec![
-                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".to_string()),
-                Principal::ContentPrincipal("https://example.com/".to_string())])
+                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".parse().unwrap()),
+                Principal::ContentPrincipal("https://example.com/".parse().unwrap())])
         );
     }
 
@@ -181,8 +441,8 @@ mod tests_principal_from_str {
         assert_eq!(
             Principal::from_str("[Expanded Principal [https://example.com/ moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/]]").unwrap(),
             Principal::ExpandedPrincipal(vec![
-                Principal::ContentPrincipal("https://example.com/".to_string()),
-                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".to_string())])
+                Principal::ContentPrincipal("https://example.com/".parse().unwrap()),
+                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".parse().unwrap())])
         );
     }
 }
@@ -195,7 +455,7 @@ mod tests_principal_to_str {
     fn parse_http_url() {
         assert_eq!(
             "http://example.com/",
-            Principal::ContentPrincipal("http://example.com/".to_string()).to_string()
+            Principal::ContentPrincipal("http://example.com/".parse().unwrap()).to_string()
         );
     }
 
@@ -203,7 +463,7 @@ mod tests_principal_to_str {
     fn parse_about_url() {
         assert_eq!(
             "about:config",
-            Principal::ContentPrincipal("about:config".to_string()).to_string()
+            Principal::ContentPrincipal("about:config".parse().unwrap()).to_string()
         );
     }
 
@@ -222,7 +482,7 @@ mod tests_principal_to_str {
         assert_eq!(
             "[Expanded Principal [https://example.com/]]",
             Principal::ExpandedPrincipal(vec![Principal::ContentPrincipal(
-                "https://example.com/".to_string()
+                "https://example.com/".parse().unwrap()
             )])
             .to_string()
         );
@@ -233,8 +493,8 @@ mod tests_principal_to_str {
         assert_eq!(
             "[Expanded Principal [moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/ https://example.com/]]",
             Principal::ExpandedPrincipal(vec![
-                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".to_string()),
-                Principal::ContentPrincipal("https://example.com/".to_string())]).to_string()
+                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".parse().unwrap()),
+                Principal::ContentPrincipal("https://example.com/".parse().unwrap())]).to_string()
         );
     }
 
@@ -243,8 +503,185 @@ mod tests_principal_to_str {
         assert_eq!(
             "[Expanded Principal [https://example.com/ moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/]]",
             Principal::ExpandedPrincipal(vec![
-                Principal::ContentPrincipal("https://example.com/".to_string()),
-                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".to_string())]).to_string()
+                Principal::ContentPrincipal("https://example.com/".parse().unwrap()),
+                Principal::ContentPrincipal("moz-extension://3767278d-dead-beef-be81-c0ffeec0ffee/".parse().unwrap())]).to_string()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_origin_attributes {
+    use super::{OriginAttributes, Principal};
+    use std::str::FromStr;
+
+    #[test]
+    fn parses_suffix() {
+        let text =
+            "https://example.com/^userContextId=2&privateBrowsingId=1&firstPartyDomain=example.com";
+        match Principal::from_str(text).unwrap() {
+            Principal::ContentPrincipal(p) => {
+                assert_eq!(p.url.as_str(), "https://example.com/");
+                assert_eq!(p.origin_attributes.user_context_id, 2);
+                assert!(p.origin_attributes.is_private_browsing());
+                assert!(p.origin_attributes.is_container());
+                assert_eq!(p.origin_attributes.first_party_domain, "example.com");
+            }
+            other => panic!("expected a content principal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in &[
+            "https://example.com/",
+            "https://example.com/^userContextId=2",
+            "https://example.com/^privateBrowsingId=1&partitionKey=%28https%2Cexample.org%29",
+            "https://example.com/^geckoViewSessionContextId=abc&someFutureId=1",
+        ] {
+            assert_eq!(Principal::from_str(text).unwrap().to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn decodes_partition_key() {
+        let attrs =
+            OriginAttributes::from_suffix("^partitionKey=%28https%2Cexample.org%29").unwrap();
+        assert_eq!(attrs.partition_key, "(https,example.org)");
+        assert!(!attrs.is_default());
+    }
+
+    #[test]
+    fn caret_in_path_is_not_a_suffix() {
+        let p = Principal::from_str("https://example.com/a^b").unwrap();
+        assert_eq!(p.to_string(), "https://example.com/a^b");
+    }
+
+    #[test]
+    fn bad_number() {
+        assert!(OriginAttributes::from_suffix("^userContextId=two").is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests_principal_origin {
+    use super::Principal;
+    use std::str::FromStr;
+    use url::Url;
+
+    #[test]
+    fn same_origin_and_site() {
+        let p = Principal::from_str("https://www.example.com/page^userContextId=1").unwrap();
+        let same_origin = Url::parse("https://www.example.com/other").unwrap();
+        let same_site = Url::parse("https://static.example.com/").unwrap();
+        let cross_site = Url::parse("https://example.org/").unwrap();
+        assert!(p.is_same_origin(&same_origin));
+        assert!(!p.is_same_origin(&same_site));
+        assert!(p.is_same_site(&same_site));
+        assert!(!p.is_same_site(&cross_site));
+        assert_eq!(p.site(), Some("https://example.com".to_string()));
+    }
+
+    #[test]
+    fn opaque_and_non_content() {
+        let data = Url::parse("data:text/html,hi").unwrap();
+        let p = Principal::from_str("data:text/html,hi").unwrap();
+        assert!(!p.is_same_origin(&data));
+        assert!(!Principal::SystemPrincipal.is_same_site(&data));
+        assert_eq!(Principal::NullPrincipal.origin(), None);
+    }
+
+    #[test]
+    fn about_pages() {
+        let p = Principal::from_str("about:preferences").unwrap();
+        let same_page = Url::parse("about:preferences#privacy").unwrap();
+        assert!(p.is_same_origin(&same_page));
+        assert!(p.is_same_site(&same_page));
+        assert!(!p.is_same_origin(&Url::parse("about:config").unwrap()));
+        assert_eq!(p.site(), Some("about:preferences".to_string()));
+        let blank = Principal::from_str("about:blank").unwrap();
+        assert!(!blank.is_same_origin(&Url::parse("about:blank").unwrap()));
+    }
+}
+
+#[cfg(test)]
+mod tests_expanded_principal {
+    use super::Principal;
+    use std::str::FromStr;
+
+    fn content(url: &str) -> Principal {
+        Principal::ContentPrincipal(url.parse().unwrap())
+    }
+
+    #[test]
+    fn comma_separated() {
+        assert_eq!(
+            Principal::from_str(
+                "[Expanded Principal [https://example.com, https://example.org^userContextId=1]]"
+            )
+            .unwrap(),
+            Principal::ExpandedPrincipal(vec![
+                content("https://example.com"),
+                content("https://example.org^userContextId=1")
+            ])
+        );
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(
+            Principal::from_str("[Expanded Principal [https://example.com/ [Expanded Principal [moz-extension://abc/ SystemPrincipal]]]]").unwrap(),
+            Principal::ExpandedPrincipal(vec![
+                content("https://example.com/"),
+                Principal::ExpandedPrincipal(vec![
+                    content("moz-extension://abc/"),
+                    Principal::SystemPrincipal
+                ])
+            ])
+        );
+    }
+
+    #[test]
+    fn url_with_space_and_escapes() {
+        assert_eq!(
+            Principal::from_str(
+                "[Expanded Principal [https://example.com/a b https://example.com/c\\ d\\]]]"
+            )
+            .unwrap(),
+            Principal::ExpandedPrincipal(vec![
+                content("https://example.com/a b"),
+                content("https://example.com/c d]"),
+            ])
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for text in &[
+            "[Expanded Principal [",
+            "[Expanded Principal [https://example.com/",
+            "[Expanded Principal [[Expanded Principal [https://example.com/]]",
+            "[Expanded Principal [a\\]]",
+        ] {
+            let e = Principal::from_str(text).unwrap_err();
+            assert_eq!(e.input, *text);
+            assert!(!e.reason.is_empty());
+        }
+    }
+
+    #[test]
+    fn malformed_is_kept_when_deserializing() {
+        let principal: Principal = serde_yaml::from_str("'[Expanded Principal [x'").unwrap();
+        assert_eq!(
+            principal,
+            Principal::UnparsedPrincipal("[Expanded Principal [x".to_string())
+        );
+    }
+
+    #[test]
+    fn not_a_url() {
+        assert_eq!(
+            Principal::from_str("[System Principal]").unwrap(),
+            Principal::UnparsedPrincipal("[System Principal]".to_string())
         );
     }
 }
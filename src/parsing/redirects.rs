@@ -0,0 +1,81 @@
+use crate::parsing::origin::origin_of;
+use crate::parsing::principal::Principal;
+use std::str::FromStr;
+use url::Url;
+
+/// One entry of a channel's redirect chain. Gecko logs the principal of
+/// every URL that redirected, which for web content is just its URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedirectHop {
+    pub principal: Principal,
+    /// `None` for hops whose principal is not a URL, e.g. `NullPrincipal`.
+    pub url: Option<Url>,
+    /// Whether the next hop, or the channel URI for the last one, has a
+    /// different origin. Unknown if either URL is.
+    pub crosses_origin: Option<bool>,
+    /// Whether this hop was HTTPS and the next one is plain HTTP.
+    pub downgrades: bool,
+}
+
+impl RedirectHop {
+    /// Builds the hops of a chain as logged, oldest first, ending in
+    /// `channel_uri`.
+    pub fn chain(entries: &[String], channel_uri: &str) -> Vec<RedirectHop> {
+        let urls: Vec<(Principal, Option<Url>)> = entries
+            .iter()
+            .map(|entry| {
+                let principal = Principal::from_str(entry)
+                    .unwrap_or_else(|_| Principal::UnparsedPrincipal(entry.clone()));
+                let url = match &principal {
+                    Principal::ContentPrincipal(p) => Some(p.url.clone()),
+                    _ => None,
+                };
+                (principal, url)
+            })
+            .collect();
+        let channel_url = Url::parse(channel_uri).ok();
+        let mut hops = vec![];
+        for (i, (principal, url)) in urls.iter().enumerate() {
+            let next = match urls.get(i + 1) {
+                Some((_, next)) => next.as_ref(),
+                None => channel_url.as_ref(),
+            };
+            let (crosses_origin, downgrades) = match (url, next) {
+                (Some(url), Some(next)) => (
+                    Some(origin_of(url) != origin_of(next)),
+                    url.scheme() == "https" && next.scheme() == "http",
+                ),
+                _ => (None, false),
+            };
+            hops.push(RedirectHop {
+                principal: principal.clone(),
+                url: url.clone(),
+                crosses_origin,
+                downgrades,
+            });
+        }
+        hops
+    }
+}
+
+#[cfg(test)]
+mod tests_redirect_hop {
+    use super::RedirectHop;
+
+    #[test]
+    fn chain() {
+        let entries = vec![
+            "https://example.com/login".to_string(),
+            "https://example.com/next".to_string(),
+            "NullPrincipal".to_string(),
+            "https://example.org/".to_string(),
+        ];
+        let hops = RedirectHop::chain(&entries, "http://example.org/");
+        assert_eq!(hops[0].crosses_origin, Some(false));
+        assert_eq!(hops[1].crosses_origin, None);
+        assert!(hops[2].url.is_none());
+        assert_eq!(hops[3].crosses_origin, Some(true));
+        assert!(hops[3].downgrades);
+        assert!(!hops[0].downgrades);
+    }
+}
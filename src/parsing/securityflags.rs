@@ -0,0 +1,353 @@
+#![allow(clippy::upper_case_acronyms)]
+/*
+Mirrors the SEC_* constants of
+  https://searchfox.org/mozilla-central/source/netwerk/base/nsILoadInfo.idl
+*/
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use strum_macros::{EnumString, IntoStaticStr};
+
+/// A single `nsILoadInfo::SEC_*` constant.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumString, IntoStaticStr)]
+pub enum SecurityFlag {
+    SEC_ONLY_FOR_EXPLICIT_CONTENTSEC_CHECK,
+    SEC_REQUIRE_SAME_ORIGIN_INHERITS_SEC_CONTEXT,
+    SEC_REQUIRE_SAME_ORIGIN_DATA_IS_BLOCKED,
+    SEC_ALLOW_CROSS_ORIGIN_INHERITS_SEC_CONTEXT,
+    SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL,
+    SEC_REQUIRE_CORS_INHERITS_SEC_CONTEXT,
+    SEC_COOKIES_DEFAULT,
+    SEC_COOKIES_INCLUDE,
+    SEC_COOKIES_SAME_ORIGIN,
+    SEC_COOKIES_OMIT,
+    SEC_FORCE_INHERIT_PRINCIPAL,
+    SEC_FORCE_PRIVATE_BROWSING,
+    SEC_ABOUT_BLANK_INHERITS,
+    SEC_ALLOW_CHROME,
+    SEC_DISALLOW_SCRIPT,
+    SEC_DONT_FOLLOW_REDIRECTS,
+    SEC_LOAD_ERROR_PAGE,
+    SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER,
+}
+
+/// The five mutually exclusive security modes live in the lowest bits.
+pub const SEC_MODE_MASK: u32 = 0b1_1111;
+/// The cookie policy is a two bit field, not a set of independent flags.
+pub const SEC_COOKIES_MASK: u32 = 0b11 << 5;
+
+impl SecurityFlag {
+    pub const ALL: [SecurityFlag; 18] = [
+        SecurityFlag::SEC_ONLY_FOR_EXPLICIT_CONTENTSEC_CHECK,
+        SecurityFlag::SEC_REQUIRE_SAME_ORIGIN_INHERITS_SEC_CONTEXT,
+        SecurityFlag::SEC_REQUIRE_SAME_ORIGIN_DATA_IS_BLOCKED,
+        SecurityFlag::SEC_ALLOW_CROSS_ORIGIN_INHERITS_SEC_CONTEXT,
+        SecurityFlag::SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL,
+        SecurityFlag::SEC_REQUIRE_CORS_INHERITS_SEC_CONTEXT,
+        SecurityFlag::SEC_COOKIES_DEFAULT,
+        SecurityFlag::SEC_COOKIES_INCLUDE,
+        SecurityFlag::SEC_COOKIES_SAME_ORIGIN,
+        SecurityFlag::SEC_COOKIES_OMIT,
+        SecurityFlag::SEC_FORCE_INHERIT_PRINCIPAL,
+        SecurityFlag::SEC_FORCE_PRIVATE_BROWSING,
+        SecurityFlag::SEC_ABOUT_BLANK_INHERITS,
+        SecurityFlag::SEC_ALLOW_CHROME,
+        SecurityFlag::SEC_DISALLOW_SCRIPT,
+        SecurityFlag::SEC_DONT_FOLLOW_REDIRECTS,
+        SecurityFlag::SEC_LOAD_ERROR_PAGE,
+        SecurityFlag::SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER,
+    ];
+
+    /// The numeric value from `nsILoadInfo.idl`.
+    pub fn value(self) -> u32 {
+        match self {
+            SecurityFlag::SEC_ONLY_FOR_EXPLICIT_CONTENTSEC_CHECK => 0,
+            SecurityFlag::SEC_REQUIRE_SAME_ORIGIN_INHERITS_SEC_CONTEXT => 1 << 0,
+            SecurityFlag::SEC_REQUIRE_SAME_ORIGIN_DATA_IS_BLOCKED => 1 << 1,
+            SecurityFlag::SEC_ALLOW_CROSS_ORIGIN_INHERITS_SEC_CONTEXT => 1 << 2,
+            SecurityFlag::SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL => 1 << 3,
+            SecurityFlag::SEC_REQUIRE_CORS_INHERITS_SEC_CONTEXT => 1 << 4,
+            SecurityFlag::SEC_COOKIES_DEFAULT => 0 << 5,
+            SecurityFlag::SEC_COOKIES_INCLUDE => 1 << 5,
+            SecurityFlag::SEC_COOKIES_SAME_ORIGIN => 2 << 5,
+            SecurityFlag::SEC_COOKIES_OMIT => 3 << 5,
+            SecurityFlag::SEC_FORCE_INHERIT_PRINCIPAL => 1 << 7,
+            SecurityFlag::SEC_FORCE_PRIVATE_BROWSING => 1 << 8,
+            SecurityFlag::SEC_ABOUT_BLANK_INHERITS => 1 << 9,
+            SecurityFlag::SEC_ALLOW_CHROME => 1 << 10,
+            SecurityFlag::SEC_DISALLOW_SCRIPT => 1 << 11,
+            SecurityFlag::SEC_DONT_FOLLOW_REDIRECTS => 1 << 12,
+            SecurityFlag::SEC_LOAD_ERROR_PAGE => 1 << 13,
+            SecurityFlag::SEC_FORCE_INHERIT_PRINCIPAL_OVERRULE_OWNER => 1 << 14,
+        }
+    }
+
+    /// The bits that have to be compared to tell whether this flag is set.
+    fn mask(self) -> u32 {
+        match self {
+            SecurityFlag::SEC_ONLY_FOR_EXPLICIT_CONTENTSEC_CHECK => SEC_MODE_MASK,
+            SecurityFlag::SEC_COOKIES_DEFAULT
+            | SecurityFlag::SEC_COOKIES_INCLUDE
+            | SecurityFlag::SEC_COOKIES_SAME_ORIGIN
+            | SecurityFlag::SEC_COOKIES_OMIT => SEC_COOKIES_MASK,
+            other => other.value(),
+        }
+    }
+
+    pub fn is_cookie_policy(self) -> bool {
+        self.mask() == SEC_COOKIES_MASK
+    }
+}
+
+impl fmt::Display for SecurityFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(<&'static str>::from(*self))
+    }
+}
+
+/// The `securityFlags` of a load, as a typed set.
+///
+/// Gecko logs every constant `c` for which `flags & c` is non-zero. For the
+/// two bit cookie policy that means `SEC_COOKIES_OMIT` shows up as INCLUDE,
+/// SAME_ORIGIN and OMIT, and `SEC_COOKIES_INCLUDE` as INCLUDE and OMIT. We
+/// keep the names as logged and decode them into the actual bits.
+/// Names we do not know are kept as they are.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityFlags {
+    logged: BTreeSet<SecurityFlag>,
+    unknown: Vec<String>,
+}
+
+impl SecurityFlags {
+    /// Builds the set from names as they appear in a log. Unknown names are
+    /// kept, see `unknown()`.
+    pub fn from_logged<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut flags = SecurityFlags::default();
+        for name in names {
+            match SecurityFlag::from_str(name.as_ref()) {
+                Ok(flag) => {
+                    flags.logged.insert(flag);
+                }
+                Err(_) => flags.unknown.push(name.as_ref().to_string()),
+            }
+        }
+        flags
+    }
+
+    /// The flags Gecko would log for `bits`. Bits no known flag covers are dropped.
+    pub fn from_bits(bits: u32) -> Self {
+        SecurityFlags {
+            logged: SecurityFlag::ALL
+                .iter()
+                .copied()
+                .filter(|flag| bits & flag.value() != 0)
+                .collect(),
+            unknown: vec![],
+        }
+    }
+
+    /// The numeric `nsSecurityFlags` value.
+    pub fn bits(&self) -> u32 {
+        let mut bits = 0;
+        for flag in &self.logged {
+            if !flag.is_cookie_policy() {
+                bits |= flag.value();
+            }
+        }
+        let has = |flag| self.logged.contains(&flag);
+        let mut cookies = 0;
+        if has(SecurityFlag::SEC_COOKIES_INCLUDE) {
+            cookies |= SecurityFlag::SEC_COOKIES_INCLUDE.value();
+        }
+        if has(SecurityFlag::SEC_COOKIES_SAME_ORIGIN) {
+            cookies |= SecurityFlag::SEC_COOKIES_SAME_ORIGIN.value();
+        }
+        if cookies == 0 && has(SecurityFlag::SEC_COOKIES_OMIT) {
+            cookies = SecurityFlag::SEC_COOKIES_OMIT.value();
+        }
+        bits | cookies
+    }
+
+    /// Whether `flag` is in effect. For the cookie policy and the
+    /// zero-valued flags this compares the whole field, e.g. a load with
+    /// `SEC_COOKIES_OMIT` does not contain `SEC_COOKIES_INCLUDE`.
+    pub fn contains(&self, flag: SecurityFlag) -> bool {
+        self.bits() & flag.mask() == flag.value()
+    }
+
+    pub fn insert(&mut self, flag: SecurityFlag) {
+        let bits = (self.bits() & !flag.mask()) | flag.value();
+        self.logged = SecurityFlags::from_bits(bits).logged;
+    }
+
+    /// The non-zero flags in effect, in `nsILoadInfo.idl` order.
+    pub fn iter(&self) -> impl Iterator<Item = SecurityFlag> + '_ {
+        SecurityFlag::ALL
+            .iter()
+            .copied()
+            .filter(move |flag| flag.value() != 0 && self.contains(*flag))
+    }
+
+    /// The known flags exactly as they were logged.
+    pub fn logged(&self) -> impl Iterator<Item = SecurityFlag> + '_ {
+        self.logged.iter().copied()
+    }
+
+    /// Flag names we did not recognize, e.g. from a newer Firefox.
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logged.is_empty() && self.unknown.is_empty()
+    }
+}
+
+impl PartialEq for SecurityFlags {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits() == other.bits() && self.unknown == other.unknown
+    }
+}
+
+impl Eq for SecurityFlags {}
+
+impl FromStr for SecurityFlags {
+    type Err = String;
+
+    /// Parses user input like `SEC_ALLOW_CHROME | SEC_COOKIES_OMIT`.
+    /// Unlike `from_logged`, unknown names are an error.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut flags = SecurityFlags::default();
+        for name in text
+            .split(|c: char| c == '|' || c == ',' || c.is_whitespace())
+            .filter(|name| !name.is_empty())
+        {
+            match SecurityFlag::from_str(name) {
+                Ok(flag) => flags.insert(flag),
+                Err(_) => return Err(format!("unknown security flag {}", name)),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+impl fmt::Display for SecurityFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = self
+            .iter()
+            .map(|flag| -> &str { flag.into() })
+            .chain(self.unknown.iter().map(|s| s.as_str()))
+            .collect();
+        write!(f, "{}", names.join(" | "))
+    }
+}
+
+impl Serialize for SecurityFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.logged.len() + self.unknown.len()))?;
+        for flag in &self.logged {
+            seq.serialize_element(<&'static str>::from(*flag))?;
+        }
+        for name in &self.unknown {
+            seq.serialize_element(name)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for SecurityFlags {
+    fn deserialize<D>(deserializer: D) -> Result<SecurityFlags, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // An empty `- securityFlags:` comes out as null.
+        let names: Option<Vec<String>> = Deserialize::deserialize(deserializer)?;
+        Ok(SecurityFlags::from_logged(&names.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests_security_flags {
+    use super::{SecurityFlag, SecurityFlags};
+    use std::str::FromStr;
+
+    #[test]
+    fn decodes_logged_cookie_omit() {
+        let flags = SecurityFlags::from_logged(&[
+            "SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL",
+            "SEC_COOKIES_INCLUDE",
+            "SEC_COOKIES_SAME_ORIGIN",
+            "SEC_COOKIES_OMIT",
+        ]);
+        assert_eq!(flags.bits(), 8 | 96);
+        assert!(flags.contains(SecurityFlag::SEC_COOKIES_OMIT));
+        assert!(!flags.contains(SecurityFlag::SEC_COOKIES_INCLUDE));
+        assert_eq!(
+            flags.to_string(),
+            "SEC_ALLOW_CROSS_ORIGIN_SEC_CONTEXT_IS_NULL | SEC_COOKIES_OMIT"
+        );
+    }
+
+    #[test]
+    fn decodes_logged_cookie_include() {
+        let flags = SecurityFlags::from_logged(&["SEC_COOKIES_INCLUDE", "SEC_COOKIES_OMIT"]);
+        assert_eq!(flags.bits(), 32);
+        assert!(flags.contains(SecurityFlag::SEC_COOKIES_INCLUDE));
+        assert!(!flags.contains(SecurityFlag::SEC_COOKIES_OMIT));
+    }
+
+    #[test]
+    fn from_bits_logs_like_gecko() {
+        let flags = SecurityFlags::from_bits(96 | 1024);
+        let logged: Vec<_> = flags.logged().collect();
+        assert_eq!(
+            logged,
+            vec![
+                SecurityFlag::SEC_COOKIES_INCLUDE,
+                SecurityFlag::SEC_COOKIES_SAME_ORIGIN,
+                SecurityFlag::SEC_COOKIES_OMIT,
+                SecurityFlag::SEC_ALLOW_CHROME,
+            ]
+        );
+        assert_eq!(flags.bits(), 96 | 1024);
+    }
+
+    #[test]
+    fn zero_valued_flags() {
+        let flags = SecurityFlags::from_logged(&["SEC_ALLOW_CHROME"]);
+        assert!(flags.contains(SecurityFlag::SEC_COOKIES_DEFAULT));
+        assert!(flags.contains(SecurityFlag::SEC_ONLY_FOR_EXPLICIT_CONTENTSEC_CHECK));
+        let flags = SecurityFlags::from_logged(&["SEC_REQUIRE_CORS_INHERITS_SEC_CONTEXT"]);
+        assert!(!flags.contains(SecurityFlag::SEC_ONLY_FOR_EXPLICIT_CONTENTSEC_CHECK));
+    }
+
+    #[test]
+    fn keeps_unknown_flags() {
+        let flags = SecurityFlags::from_logged(&["SEC_ALLOW_CHROME", "SEC_FROM_THE_FUTURE"]);
+        assert_eq!(flags.unknown(), &["SEC_FROM_THE_FUTURE".to_string()]);
+        assert_eq!(flags.to_string(), "SEC_ALLOW_CHROME | SEC_FROM_THE_FUTURE");
+    }
+
+    #[test]
+    fn from_str_rejects_typos() {
+        let flags = SecurityFlags::from_str("SEC_ALLOW_CHROME | SEC_COOKIES_OMIT").unwrap();
+        assert_eq!(flags.bits(), 1024 | 96);
+        assert!(SecurityFlags::from_str("SEC_ALLOW_CHROM").is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let flags = SecurityFlags::from_logged(&["SEC_COOKIES_INCLUDE", "SEC_COOKIES_OMIT", "X"]);
+        let yaml = serde_yaml::to_string(&flags).unwrap();
+        let back: SecurityFlags = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(back, flags);
+        assert_eq!(back.logged().count(), 2);
+    }
+}
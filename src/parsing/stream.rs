@@ -0,0 +1,281 @@
+use crate::parsing::checktypes::{unknown_field, ContentSecurityCheck};
+use crate::parsing::error::{ErrorContext, ParseError};
+use crate::parsing::{parse_timestamp, parsed_content_security_check, LogLevel, ProcessType};
+use log::{debug, info, warn};
+
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::str::FromStr;
+
+const BEGIN_BLOCK: &str = "#DebugDoContentSecurityCheck Begin";
+const END_BLOCK: &str = "#DebugDoContentSecurityCheck End";
+
+/// Identifies one writer in a multi-process log: `[Child 1234: DOM Worker]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StreamKey {
+    process: String,
+    pid: Option<u32>,
+    thread: String,
+}
+
+impl From<&Captures<'_>> for StreamKey {
+    fn from(caps: &Captures) -> Self {
+        StreamKey {
+            process: caps["process"].to_string(),
+            pid: caps["pid"].parse().ok(),
+            thread: caps["thread"].to_string(),
+        }
+    }
+}
+
+/// A block that has seen its `Begin` but not yet its `End`.
+#[derive(Debug)]
+struct OpenBlock {
+    /// `None` until we have seen a prefixed line for it, e.g. when a log
+    /// starts right with a `Begin` marker.
+    key: Option<StreamKey>,
+    start: usize,
+    lines: Vec<String>,
+    log_level: Option<LogLevel>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+fn process_type_from(process: &str) -> ProcessType {
+    match process {
+        "Child" => ProcessType::Child,
+        "Parent" => ProcessType::Parent,
+        _ => {
+            warn!("Noticed unknown string for process type: {}", process);
+            ProcessType::Unknown
+        }
+    }
+}
+
+/// Lazily parses `ContentSecurityCheck`s out of a MOZ_LOG, one block at a
+/// time, so that arbitrarily large logs can be scanned in constant memory.
+///
+/// Lines from different processes and threads may interleave freely; each
+/// of them gets its own in-progress block. The unprefixed `Begin`/`End`
+/// markers are attributed to the writer of the preceding CSMLog line, as
+/// Gecko logs them as part of that line's message.
+pub struct CheckStream<R: BufRead> {
+    reader: R,
+    source: String,
+    is_csmlog_line: Regex,
+    is_mozlog_line: Regex,
+    is_cut_prefix: Regex,
+    buf: Vec<u8>,
+    lineno: usize,
+    last_key: Option<StreamKey>,
+    open: Vec<OpenBlock>,
+    ready: VecDeque<Result<ContentSecurityCheck, ParseError>>,
+    blockcnt: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> CheckStream<R> {
+    pub fn new(reader: R) -> Self {
+        CheckStream::with_source("<unnamed>", reader)
+    }
+
+    /// Like `new`, but errors and checks will name `source` as their origin.
+    pub fn with_source(source: &str, reader: R) -> Self {
+        CheckStream {
+            reader,
+            source: source.to_string(),
+            is_csmlog_line: Regex::new(concat!(
+                r"(?:(?P<timestamp>\d{4}-\d\d-\d\d \d\d:\d\d:\d\d(?:\.\d+)?) UTC - )?",
+                r"\[(?P<process>\w+) (?P<pid>\d+): (?P<thread>[^\]]+)]: ",
+                r"(?P<level>[EWIDV])/CSMLog(?: (?P<message>.*))?$"
+            ))
+            .unwrap(),
+            is_mozlog_line: Regex::new(r"\[\w+ \d+: [^\]]*]: [A-Z]/\w+").unwrap(),
+            is_cut_prefix: Regex::new(concat!(
+                r"^(?:\d{4}-\d\d-\d\d [\d:.]+ UTC - )?\[",
+                r"(?:[\w :]*|\w+ \d+: [^\]]*](?::(?: (?:[EWIDV](?:/\w*)?)?)?)?)$"
+            ))
+            .unwrap(),
+            buf: Vec::with_capacity(256),
+            lineno: 0,
+            last_key: None,
+            open: vec![],
+            ready: VecDeque::new(),
+            blockcnt: 0,
+            finished: false,
+        }
+    }
+
+    /// Index of the open block written by `key`. Falls back to a block that
+    /// has not been attributed to any writer yet.
+    fn find_open(&self, key: &Option<StreamKey>) -> Option<usize> {
+        self.open
+            .iter()
+            .position(|b| &b.key == key)
+            .or_else(|| self.open.iter().position(|b| b.key.is_none()))
+    }
+
+    fn context(&self, block: OpenBlock) -> ErrorContext {
+        ErrorContext {
+            file: self.source.clone(),
+            line: block.start,
+            block: block.lines.join("\n"),
+        }
+    }
+
+    fn finish_block(&self, block: OpenBlock) -> Result<ContentSecurityCheck, ParseError> {
+        // `parsed_content_security_check` wants to own the lines, but we
+        // need them again for the error context.
+        match parsed_content_security_check(ProcessType::Unknown, block.lines.clone()) {
+            Ok(mut check) => {
+                if let Some(key) = block.key {
+                    check.process_type = process_type_from(&key.process);
+                    check.pid = key.pid;
+                    check.thread = Some(key.thread);
+                }
+                check.source = Some(self.source.clone());
+                check.log_level = block.log_level;
+                check.timestamp = block.timestamp;
+                Ok(check)
+            }
+            Err(error) => {
+                let context = self.context(block);
+                Err(match unknown_field(&context.block) {
+                    Some(field) => ParseError::UnknownField { context, field },
+                    None => ParseError::Yaml { context, error },
+                })
+            }
+        }
+    }
+
+    /// Routes one line to the block it belongs to, queueing finished checks
+    /// and errors in `self.ready`.
+    fn handle_line(&mut self, line: &str) {
+        if line == BEGIN_BLOCK {
+            self.blockcnt += 1;
+            if let Some(i) = self.open.iter().position(|b| b.key == self.last_key) {
+                let block = self.open.remove(i);
+                let context = self.context(block);
+                self.ready
+                    .push_back(Err(ParseError::UnterminatedBlock { context }));
+            }
+            self.open.push(OpenBlock {
+                key: self.last_key.clone(),
+                start: self.lineno,
+                lines: Vec::with_capacity(30),
+                log_level: None,
+                timestamp: None,
+            });
+        } else if line == END_BLOCK {
+            if let Some(i) = self.find_open(&self.last_key) {
+                let block = self.open.remove(i);
+                let result = self.finish_block(block);
+                self.ready.push_back(result);
+            }
+        } else if let Some(caps) = self.is_csmlog_line.captures(line) {
+            let key = Some(StreamKey::from(&caps));
+            let level = LogLevel::from_str(&caps["level"]).ok();
+            let logged_line = caps.name("message").map_or("", |m| m.as_str()).to_string();
+            if let Some(i) = self.find_open(&key) {
+                let block = &mut self.open[i];
+                block.key = key.clone();
+                block.log_level = block.log_level.max(level);
+                if block.timestamp.is_none() {
+                    block.timestamp = caps
+                        .name("timestamp")
+                        .and_then(|m| parse_timestamp(m.as_str()));
+                }
+                block.lines.push(logged_line);
+            }
+            self.last_key = key;
+        } else if self.is_mozlog_line.is_match(line) {
+            debug!("skipping line from another log module: {}", line);
+        } else if line.contains("CSMLog") || self.is_cut_prefix.is_match(line) {
+            if let Some(i) = self.open.iter().position(|b| b.key == self.last_key) {
+                let block = self.open.remove(i);
+                let context = self.context(block);
+                self.ready.push_back(Err(ParseError::MalformedPrefix {
+                    context,
+                    lineno: self.lineno,
+                    line: line.to_string(),
+                }));
+            }
+        } else if !line.is_empty() {
+            // Firefox writes plenty of other things to stderr, e.g.
+            // `JavaScript error: ...`, right between the lines of a block.
+            warn!("skipping line without a MOZ_LOG prefix: {}", line);
+        }
+    }
+
+    /// Drops the block a line that is not valid UTF-8 would have gone to.
+    fn handle_invalid_utf8(&mut self, lossy: &str) {
+        let key = match self.is_csmlog_line.captures(lossy) {
+            Some(caps) => Some(StreamKey::from(&caps)),
+            None => self.last_key.clone(),
+        };
+        if let Some(i) = self.find_open(&key) {
+            let block = self.open.remove(i);
+            let context = self.context(block);
+            self.ready.push_back(Err(ParseError::InvalidUtf8 {
+                context,
+                lineno: self.lineno,
+            }));
+        }
+    }
+
+    /// Reads the next line. `Ok(None)` is the end of input; the flag tells
+    /// whether the line was valid UTF-8 (if not, it was decoded lossily).
+    fn read_line(&mut self) -> std::io::Result<Option<(String, bool)>> {
+        self.buf.clear();
+        if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+            return Ok(None);
+        }
+        self.lineno += 1;
+        if self.buf.ends_with(b"\n") {
+            self.buf.pop();
+            if self.buf.ends_with(b"\r") {
+                self.buf.pop();
+            }
+        }
+        Ok(Some(match std::str::from_utf8(&self.buf) {
+            Ok(line) => (line.to_string(), true),
+            Err(_) => (String::from_utf8_lossy(&self.buf).into_owned(), false),
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for CheckStream<R> {
+    type Item = Result<ContentSecurityCheck, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.finished {
+            match self.read_line() {
+                Ok(Some((line, true))) => self.handle_line(&line),
+                Ok(Some((line, false))) => self.handle_invalid_utf8(&line),
+                Ok(None) => {
+                    self.finished = true;
+                    info!(
+                        "Finished parsing {} lines and {} blocks of {}",
+                        self.lineno, self.blockcnt, self.source
+                    );
+                    for block in std::mem::take(&mut self.open) {
+                        let context = self.context(block);
+                        self.ready
+                            .push_back(Err(ParseError::UnterminatedBlock { context }));
+                    }
+                }
+                Err(error) => {
+                    self.finished = true;
+                    let context = ErrorContext {
+                        file: self.source.clone(),
+                        line: self.lineno,
+                        block: String::new(),
+                    };
+                    self.ready.push_back(Err(ParseError::Io { context, error }));
+                }
+            }
+        }
+        self.ready.pop_front()
+    }
+}
@@ -1,5 +1,27 @@
 #[cfg(test)]
 pub(crate) mod fixtures {
+    use crate::analysis::rules::Rule;
+    use crate::parsing::checktypes::ContentSecurityCheck;
+    use crate::parsing::{parsed_content_security_check, ProcessType};
+
+    /// Parses a prefix-free block like the ones below.
+    pub(crate) fn parse(block: &str) -> ContentSecurityCheck {
+        let lines = block.split('\n').map(String::from).collect();
+        parsed_content_security_check(ProcessType::Unknown, lines).unwrap()
+    }
+
+    /// Those of `rules` that found something about `check`.
+    pub(crate) fn rules_finding<R: Rule + Copy>(
+        rules: &[R],
+        check: &ContentSecurityCheck,
+    ) -> Vec<R> {
+        rules
+            .iter()
+            .filter(|rule| rule.check(check).is_some())
+            .copied()
+            .collect()
+    }
+
     pub(crate) const SAMPLE_BLOCK: &str = r"doContentSecurityCheck:
   - channelURI: https://incoming.telemetry.mozilla.org/submit/telemetry/b0a4b2dc-c5b7-44ed-b0d4-41e01a9abf4e/bhr/Firefox/89.0a1/nightly/20210412213434?v=4
   - httpMethod: POST
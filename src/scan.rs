@@ -0,0 +1,332 @@
+//! Runs the picked rules over a set of sources and prints what they find.
+
+use crate::analysis::csplint::CspCorpus;
+use crate::analysis::filter::Filter;
+use crate::analysis::rules::{Finding, RuleSet};
+use crate::input::InputSource;
+use crate::parsing::checktypes::ContentSecurityCheck;
+use crate::parsing::policytypes::{nsContentPolicyType, PolicyCategory};
+use crate::parsing::{CheckStream, ParseError};
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+
+/// What to scan for and how to print it.
+pub struct ScanOptions {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub strict: bool,
+    pub lint_csp: bool,
+    /// Only consider checks whose internal type maps to one of these.
+    pub external_types: Vec<nsContentPolicyType>,
+    pub categories: Vec<PolicyCategory>,
+    /// Only consider checks matching this, and report all of them.
+    pub filter: Option<Filter>,
+    pub rules: RuleSet,
+    /// Print the results ordered by their log timestamp, once all sources
+    /// are done.
+    pub sort_by_time: bool,
+    pub verbose: bool,
+    /// Break the number of scanned checks down by category.
+    pub by_category: bool,
+    /// How many sources to parse in parallel, 0 for one per core.
+    pub jobs: usize,
+}
+
+/// Prints what the rules found about `check`, followed by the whole check if
+/// `verbose` or there is nothing else to show.
+fn print_findings(check: &ContentSecurityCheck, findings: &[Finding], verbose: bool) {
+    for finding in findings {
+        println!(
+            "{}:{}: {}",
+            check.source().unwrap_or_default(),
+            check.channel_uri(),
+            finding
+        );
+    }
+    if verbose || findings.is_empty() {
+        println!("{:?}", check);
+    }
+}
+
+/// Whether a check falls into the `[since, until)` window. Checks without a
+/// timestamp only pass if no window was requested.
+fn within(
+    c: &ContentSecurityCheck,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> bool {
+    match c.timestamp() {
+        Some(t) => since.is_none_or(|s| t >= s) && until.is_none_or(|u| t < u),
+        None => since.is_none() && until.is_none(),
+    }
+}
+
+/// What scanning a single source emits as it goes, in line order.
+enum ScanEvent {
+    /// A check at least one rule had something to say about, or any check
+    /// matching `--filter`.
+    Interesting(Box<ContentSecurityCheck>, Vec<Finding>),
+    /// A block we had to skip. In strict mode scanning stops at the first one.
+    Error(ParseError),
+    /// The source is done.
+    Done(SourceReport),
+}
+
+/// How many events a source may be ahead of the one being printed before it
+/// has to wait.
+const EVENTS_PER_SOURCE: usize = 1024;
+
+/// The totals of a single source.
+struct SourceReport {
+    source: String,
+    checkcnt: usize,
+    by_category: BTreeMap<PolicyCategory, usize>,
+    /// Policies seen, only collected with `--lint-csp`.
+    csp: CspCorpus,
+    /// Why the source could not be read to its end. The totals cover what
+    /// was read before.
+    io_error: Option<io::Error>,
+}
+
+/// Scans one source. `failed` is set once a block failed to parse, or a
+/// source could not be read, in strict mode. No new sources are started
+/// after that.
+fn scan_source(
+    source: &InputSource,
+    opts: &ScanOptions,
+    failed: &AtomicBool,
+    emit: &dyn Fn(ScanEvent),
+) -> SourceReport {
+    let mut report = SourceReport {
+        source: source.to_string(),
+        checkcnt: 0,
+        by_category: BTreeMap::new(),
+        csp: CspCorpus::new(),
+        io_error: None,
+    };
+    if failed.load(Ordering::SeqCst) {
+        return report;
+    }
+    info!("Reading {}", source);
+    let reader = match source.open() {
+        Ok(reader) => reader,
+        Err(e) => {
+            report.io_error = Some(e);
+            if opts.strict {
+                failed.store(true, Ordering::SeqCst);
+            }
+            return report;
+        }
+    };
+    for result in CheckStream::with_source(&report.source, reader) {
+        let check = match result {
+            Ok(check) => check,
+            Err(ParseError::Io { error, .. }) => {
+                report.io_error = Some(error);
+                if opts.strict {
+                    failed.store(true, Ordering::SeqCst);
+                }
+                break;
+            }
+            Err(e) => {
+                emit(ScanEvent::Error(e));
+                if opts.strict {
+                    failed.store(true, Ordering::SeqCst);
+                    break;
+                }
+                continue;
+            }
+        };
+        if !within(&check, opts.since, opts.until) {
+            continue;
+        }
+        if !opts.external_types.is_empty()
+            && !opts
+                .external_types
+                .iter()
+                .any(|t| check.internal_content_policy_type().to_external() == *t)
+        {
+            continue;
+        }
+        let category = check.internal_content_policy_type().category();
+        if !opts.categories.is_empty() && !opts.categories.contains(&category) {
+            continue;
+        }
+        if opts.filter.as_ref().is_some_and(|f| !f.matches(&check)) {
+            continue;
+        }
+        *report.by_category.entry(category).or_insert(0) += 1;
+        report.checkcnt += 1;
+        if opts.lint_csp {
+            report.csp.add(&check);
+        }
+        let findings = opts.rules.check(&check);
+        if !findings.is_empty() || opts.filter.is_some() {
+            emit(ScanEvent::Interesting(Box::new(check), findings));
+        }
+    }
+    report
+}
+
+/// Prints what the sources emit and sums up their reports.
+struct Output {
+    strict: bool,
+    sort_by_time: bool,
+    verbose: bool,
+    /// Set by the first error in strict mode. Nothing is printed after it.
+    failed: bool,
+    /// Held back with `--sort-by-time`.
+    results: Vec<(ContentSecurityCheck, Vec<Finding>)>,
+    skipped: BTreeMap<&'static str, usize>,
+    /// Sources that could not be read to their end.
+    unreadable: Vec<String>,
+    checkcnt: usize,
+    by_category: BTreeMap<PolicyCategory, usize>,
+    csp: CspCorpus,
+}
+
+impl Output {
+    /// Handles one event of the source being printed.
+    fn handle(&mut self, event: ScanEvent) {
+        if self.failed {
+            return;
+        }
+        match event {
+            ScanEvent::Interesting(check, findings) => {
+                if self.sort_by_time {
+                    self.results.push((*check, findings));
+                } else {
+                    print_findings(&check, &findings, self.verbose);
+                }
+            }
+            ScanEvent::Error(e) => {
+                if self.strict {
+                    eprintln!("{}\n--\n{}\n--", e, e.context().block);
+                    self.failed = true;
+                    return;
+                }
+                error!(
+                    "We had to skip a block, because it was not parsable: {}\n--\n{}\n--",
+                    e,
+                    e.context().block
+                );
+                *self.skipped.entry(e.kind()).or_insert(0) += 1;
+            }
+            ScanEvent::Done(report) => {
+                if let Some(e) = report.io_error {
+                    if self.strict {
+                        eprintln!("can not read {}: {}", report.source, e);
+                        self.failed = true;
+                        return;
+                    }
+                    error!("can not read {}: {}", report.source, e);
+                    self.unreadable.push(report.source);
+                }
+                self.checkcnt += report.checkcnt;
+                self.csp.merge(report.csp);
+                for (category, count) in report.by_category {
+                    *self.by_category.entry(category).or_insert(0) += count;
+                }
+            }
+        }
+    }
+
+    /// Prints the held back results and the totals.
+    fn finish(self, by_category: bool) {
+        // Checks without a timestamp sort last.
+        let mut results = self.results;
+        results.sort_by_key(|(c, _)| (c.timestamp().is_none(), c.timestamp()));
+        for (check, findings) in results {
+            print_findings(&check, &findings, self.verbose);
+        }
+
+        for (origin, policies) in self.csp.lint() {
+            println!("CSPs of {}:", origin);
+            for linted in policies {
+                println!("  {} (seen {} times)", linted.policy, linted.seen);
+                for lint in linted.lints {
+                    println!("    {}", lint);
+                }
+            }
+        }
+
+        println!("checks scanned: {}", self.checkcnt);
+        if by_category {
+            for (category, count) in self.by_category {
+                println!("  {:>6} {}", count, category);
+            }
+        }
+        if !self.skipped.is_empty() {
+            let total: usize = self.skipped.values().sum();
+            eprintln!("skipped {} unparsable blocks:", total);
+            for (kind, count) in self.skipped {
+                eprintln!("  {:>6} {}", count, kind);
+            }
+        }
+        if !self.unreadable.is_empty() {
+            eprintln!("could not read {} sources:", self.unreadable.len());
+            for source in &self.unreadable {
+                eprintln!("  {}", source);
+            }
+        }
+        println!("that's all that were interesting.");
+    }
+}
+
+/// Scans `sources` and prints what the rules find, followed by the totals.
+/// Returns whether every source could be read and, in strict mode, every
+/// block parsed. Strict mode prints nothing after the first failure.
+pub fn scan(sources: &[InputSource], opts: &ScanOptions) -> io::Result<bool> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(opts.jobs)
+        .build()
+        .map_err(io::Error::other)?;
+    let failed = AtomicBool::new(false);
+    let mut output = Output {
+        strict: opts.strict,
+        sort_by_time: opts.sort_by_time,
+        verbose: opts.verbose,
+        failed: false,
+        results: vec![],
+        skipped: BTreeMap::new(),
+        unreadable: vec![],
+        checkcnt: 0,
+        by_category: BTreeMap::new(),
+        csp: CspCorpus::new(),
+    };
+    // Sources are started in file order. What the one being printed emits
+    // is printed right away, so piped input shows up as it is parsed; the
+    // others wait once they are `EVENTS_PER_SOURCE` ahead. That never
+    // blocks the source being printed: it was started before all of them.
+    pool.in_place_scope_fifo(|scope| {
+        let mut receivers = vec![];
+        for source in sources {
+            let (sender, receiver) = mpsc::sync_channel(EVENTS_PER_SOURCE);
+            receivers.push(receiver);
+            let failed = &failed;
+            scope.spawn_fifo(move |_| {
+                let emit = |event| {
+                    let _ = sender.send(event);
+                };
+                let report = scan_source(source, opts, failed, &emit);
+                emit(ScanEvent::Done(report));
+            });
+        }
+        for receiver in receivers {
+            for event in receiver {
+                output.handle(event);
+            }
+        }
+    });
+    if output.failed {
+        return Ok(false);
+    }
+    let complete = output.unreadable.is_empty();
+    output.finish(opts.by_category);
+    Ok(complete)
+}